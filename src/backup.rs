@@ -0,0 +1,83 @@
+//! Numbered backups of configuration files
+
+use crate::ConfyError;
+use crate::lock::FileLock;
+use crate::utils::{resolve_symlink, write_atomically};
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::path::{Path, PathBuf};
+
+/// Get the path of a backup of the configuration file at `path`
+///
+/// Backups are named like the configuration file with the generation
+/// appended, `config.toml.1` is the most recent one, `config.toml.2` the one
+/// before and so on. See [`store_path_backups`] for creating them.
+///
+/// [`store_path_backups`]: fn.store_path_backups.html
+pub fn backup_path(path: impl AsRef<Path>, generation: usize) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(".{generation}"));
+    path.with_file_name(name)
+}
+
+/// Roll the configuration file at `path` back to one of its backups
+///
+/// The configuration file is replaced with the backup of the given
+/// `generation`, where `1` is the most recent one, see [`backup_path`]. The
+/// backups themselves are left as they are, so restoring can be repeated with
+/// another generation if the first one turns out to be bad as well. A missing
+/// backup fails with [`ConfyError::BackupNotFound`].
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # fn main() -> Result<(), ConfyError> {
+/// // the configuration stored last does not work, go back to the one before
+/// confy::restore_backup("my-config.toml", 1)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`backup_path`]: fn.backup_path.html
+pub fn restore_backup(path: impl AsRef<Path>, generation: usize) -> Result<(), ConfyError> {
+    let path = path.as_ref();
+    let _lock = FileLock::exclusive(path)?;
+    let target = resolve_symlink(path);
+    let backup = backup_path(&target, generation);
+    let bytes = match fs::read(&backup) {
+        Ok(bytes) => bytes,
+        Err(ref e) if e.kind() == NotFound => return Err(ConfyError::BackupNotFound(backup)),
+        Err(e) => return Err(ConfyError::ReadConfigurationFileError(e)),
+    };
+    write_atomically(&target, &bytes, None)
+}
+
+/// Keep the current contents of `path` as the newest of `count` backups,
+/// shifting the older ones down and dropping the oldest.
+///
+/// Backups beyond `count`, left from storing with more of them before, are
+/// removed as well. Without backups, the existing ones are left alone.
+pub(crate) fn rotate(path: &Path, count: usize) -> Result<(), ConfyError> {
+    if count == 0 || !path.is_file() {
+        return Ok(());
+    }
+    for generation in count + 1.. {
+        match fs::remove_file(backup_path(path, generation)) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == NotFound => break,
+            Err(e) => return Err(ConfyError::BackupError(e)),
+        }
+    }
+    for generation in (1..count).rev() {
+        let from = backup_path(path, generation);
+        match fs::rename(&from, backup_path(path, generation + 1)) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == NotFound => {}
+            Err(e) => return Err(ConfyError::BackupError(e)),
+        }
+    }
+    // a copy rather than a hard link, editors writing in place would change both
+    fs::copy(path, backup_path(path, 1)).map_err(ConfyError::BackupError)?;
+    Ok(())
+}
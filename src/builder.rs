@@ -0,0 +1,712 @@
+//! Configuration handles carrying their own settings
+
+use crate::location::{self, ConfigLocation, LocationSource};
+use crate::merged::{Merged, load_path_merged_format, upgrade_path_format};
+use crate::migrate::{self, Migrations, load_path_migrated_format};
+use crate::{
+    ConfigStrategy, ConfyError, Format, InternalStrategy, STRATEGY, load_path_format,
+    restore_backup, store_bytes, store_path_format, try_load_path_format, try_read_path,
+    update_path_format,
+};
+use etcetera::{AppStrategy, AppStrategyArgs};
+use serde::{Serialize, de::DeserializeOwned};
+use std::ffi::OsString;
+use std::fs::Permissions;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The qualifier used unless another one is set.
+const DEFAULT_QUALIFIER: &str = "rs";
+
+/// The kinds of directories files can be kept in.
+#[derive(Debug, Clone, Copy)]
+enum Dir {
+    Config,
+    Data,
+    Cache,
+    State,
+}
+
+/// A handle to the configuration of one application.
+///
+/// Unlike the free functions like [`load`] and [`store`], which share the
+/// process wide strategy set by [`change_config_strategy`], every `Confy`
+/// carries its own application name, [`ConfigStrategy`] and [`Format`]. This
+/// way libraries and tests running in the same process do not interfere
+/// with each other.
+///
+/// Together with the application name, the optional qualifier and
+/// organization identify the application to the operating system, see
+/// [`ConfyBuilder::qualifier`].
+///
+/// ```rust,no_run
+/// # use confy::{ConfigStrategy, Confy, ConfyError, Format};
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {}
+///
+/// let confy = Confy::builder()
+///     .app_name("my-app-name")
+///     .qualifier("com")
+///     .organization("Acme Corp")
+///     .strategy(ConfigStrategy::Native)
+///     .format(Format::Toml)
+///     .build()?;
+///
+/// let cfg: MyConfig = confy.load(None)?;
+/// confy.store(None, &cfg)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load`]: fn.load.html
+/// [`store`]: fn.store.html
+/// [`change_config_strategy`]: fn.change_config_strategy.html
+#[derive(Debug, Clone)]
+pub struct Confy {
+    app_name: String,
+    qualifier: String,
+    organization: String,
+    strategy: Arc<ConfigStrategy>,
+    format: Format,
+    location_env: Option<String>,
+    backups: usize,
+    create: bool,
+    migrations: Option<Migrations>,
+    write_back: bool,
+}
+
+impl Confy {
+    /// Start building a new handle, see [`ConfyBuilder`].
+    pub fn builder() -> ConfyBuilder {
+        ConfyBuilder::default()
+    }
+
+    /// The instance the free functions delegate to, using the process wide strategy.
+    pub(crate) fn global(app_name: &str) -> Confy {
+        let strategy = STRATEGY
+            .lock()
+            .expect("Error getting lock on config strategy")
+            .clone();
+        Confy {
+            app_name: app_name.to_string(),
+            qualifier: DEFAULT_QUALIFIER.to_string(),
+            organization: String::new(),
+            strategy,
+            format: Format::default(),
+            location_env: None,
+            backups: 0,
+            create: true,
+            migrations: None,
+            write_back: false,
+        }
+    }
+
+    /// This handle, upgrading configurations with `migrations` when loading them.
+    pub(crate) fn with_migrations(mut self, migrations: Migrations) -> Confy {
+        self.migrations = Some(migrations);
+        self
+    }
+
+    /// The name of the application.
+    pub fn app_name(&self) -> &str {
+        &self.app_name
+    }
+
+    /// The qualifier of the application, like the top-level domain `com`.
+    pub fn qualifier(&self) -> &str {
+        &self.qualifier
+    }
+
+    /// The organization behind the application, empty if there is none.
+    pub fn organization(&self) -> &str {
+        &self.organization
+    }
+
+    /// The strategy used to place the configuration files.
+    pub fn strategy(&self) -> &ConfigStrategy {
+        &self.strategy
+    }
+
+    /// The format of the configuration files.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// How many previous versions of a configuration file are kept when storing it.
+    pub fn backups(&self) -> usize {
+        self.backups
+    }
+
+    /// The migrations configurations are upgraded with when loading them.
+    pub fn migrations(&self) -> Option<&Migrations> {
+        self.migrations.as_ref()
+    }
+
+    /// Whether loading a configuration adds the fields missing from its file.
+    pub fn writes_back_defaults(&self) -> bool {
+        self.write_back
+    }
+
+    /// Whether loading a missing file creates it with default values.
+    pub fn creates_missing(&self) -> bool {
+        self.create
+    }
+
+    /// Get the configuration file path used by [`Confy::load`] and [`Confy::store`]
+    ///
+    /// Without a `config_name`, the file is called `default-config`. If
+    /// [`ConfyBuilder::location_env`] was set, environment variables may
+    /// override the path, see [`Confy::config_location`]. For more
+    /// information, see [`get_configuration_file_path`]'s documentation.
+    ///
+    /// [`get_configuration_file_path`]: fn.get_configuration_file_path.html
+    pub fn get_configuration_file_path<'a>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+    ) -> Result<PathBuf, ConfyError> {
+        self.config_location(config_name)
+            .map(ConfigLocation::into_path)
+    }
+
+    /// Get the configuration file path together with what decided it
+    ///
+    /// If [`ConfyBuilder::location_env`] was given a prefix, the variable
+    /// `<PREFIX>_CONFIG` names the file used for the default configuration,
+    /// the one without a `config_name`, and `<PREFIX>_CONFIG_DIR` names the
+    /// directory all configuration files are placed in. Otherwise, or if
+    /// neither variable is set, the path is derived from the strategy like
+    /// always.
+    ///
+    /// ```rust,no_run
+    /// # use confy::{Confy, ConfyError};
+    /// # fn main() -> Result<(), ConfyError> {
+    /// let confy = Confy::builder()
+    ///     .app_name("my-app-name")
+    ///     .location_env("MYAPP")
+    ///     .build()?;
+    ///
+    /// // MYAPP_CONFIG=/etc/my-app.toml my-app
+    /// let location = confy.config_location(None)?;
+    /// if location.is_override() {
+    ///     println!("using {} from {:?}", location.path().display(), location.source());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn config_location<'a>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+    ) -> Result<ConfigLocation, ConfyError> {
+        self.config_location_with(config_name.into(), |name| std::env::var_os(name))
+    }
+
+    /// [`Confy::config_location`] looking up variables with `var`.
+    pub(crate) fn config_location_with(
+        &self,
+        config_name: Option<&str>,
+        var: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<ConfigLocation, ConfyError> {
+        if let Some(prefix) = &self.location_env {
+            let file_name = self.file_name(config_name);
+            if let Some(location) = location::from_env(prefix, config_name, &file_name, var) {
+                return Ok(location);
+            }
+        }
+        let path = self.file_path(Dir::Config, config_name)?;
+        Ok(ConfigLocation::new(path, LocationSource::Strategy))
+    }
+
+    /// The configuration file and its format, which for a file named by an
+    /// environment variable follows its extension.
+    fn config_file(&self, config_name: Option<&str>) -> Result<(PathBuf, Format), ConfyError> {
+        let location = self.config_location(config_name)?;
+        let format = match location.source() {
+            LocationSource::EnvFile(_) => Format::from_path(location.path())?,
+            _ => self.format,
+        };
+        Ok((location.into_path(), format))
+    }
+
+    /// Get the path of a file in the data directory of the application
+    ///
+    /// The data directory is meant for files the application creates and
+    /// wants to keep, like a history. It is named like the configuration
+    /// file, see [`Confy::get_configuration_file_path`].
+    pub fn data_path<'a>(&self, name: impl Into<Option<&'a str>>) -> Result<PathBuf, ConfyError> {
+        self.file_path(Dir::Data, name.into())
+    }
+
+    /// Get the path of a file in the cache directory of the application
+    ///
+    /// The cache directory is meant for files which can be recreated when they
+    /// are lost. It is named like the configuration file, see
+    /// [`Confy::get_configuration_file_path`].
+    pub fn cache_path<'a>(&self, name: impl Into<Option<&'a str>>) -> Result<PathBuf, ConfyError> {
+        self.file_path(Dir::Cache, name.into())
+    }
+
+    /// Get the path of a file in the state directory of the application
+    ///
+    /// The state directory is meant for files which should survive a restart
+    /// but are not worth backing up, like the size of the last opened window.
+    /// Only the XDG and Unix layouts have a state directory, elsewhere the
+    /// data directory is used instead. It is named like the configuration
+    /// file, see [`Confy::get_configuration_file_path`].
+    pub fn state_path<'a>(&self, name: impl Into<Option<&'a str>>) -> Result<PathBuf, ConfyError> {
+        self.file_path(Dir::State, name.into())
+    }
+
+    fn file_name(&self, name: Option<&str>) -> String {
+        let name = name.unwrap_or("default-config");
+        format!("{name}.{}", self.format.extension())
+    }
+
+    fn file_path(&self, dir: Dir, name: Option<&str>) -> Result<PathBuf, ConfyError> {
+        let mut path = if let ConfigStrategy::Custom(locator) = &*self.strategy {
+            match dir {
+                Dir::Config => locator.config_dir(&self.app_name)?,
+                Dir::Data => locator.data_dir(&self.app_name)?,
+                Dir::Cache => locator.cache_dir(&self.app_name)?,
+                Dir::State => locator.state_dir(&self.app_name)?,
+            }
+        } else {
+            let strategy = InternalStrategy::new(self.strategy_args(), &self.strategy)?;
+            match dir {
+                Dir::Config => strategy.config_dir(),
+                Dir::Data => strategy.data_dir(),
+                Dir::Cache => strategy.cache_dir(),
+                Dir::State => strategy.state_dir().unwrap_or_else(|| strategy.data_dir()),
+            }
+        };
+        path.push(self.file_name(name));
+        Ok(path)
+    }
+
+    fn strategy_args(&self) -> AppStrategyArgs {
+        AppStrategyArgs {
+            top_level_domain: self.qualifier.clone(),
+            author: self.organization.clone(),
+            app_name: self.app_name.clone(),
+        }
+    }
+
+    /// Load the configuration from disk
+    ///
+    /// A new configuration file is created with default values if none
+    /// exists, unless [`ConfyBuilder::create_missing`] turned that off. With
+    /// [`ConfyBuilder::migrations`], outdated files are upgraded first, see
+    /// [`load_migrated`]. With [`ConfyBuilder::write_back_defaults`], fields
+    /// missing from the file are added to it, see [`upgrade_path`]. For more
+    /// information on errors and behavior, see [`load`]'s documentation.
+    ///
+    /// [`load_migrated`]: fn.load_migrated.html
+    /// [`upgrade_path`]: fn.upgrade_path.html
+    /// [`load`]: fn.load.html
+    pub fn load<'a, T: Serialize + DeserializeOwned + Default>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+    ) -> Result<T, ConfyError> {
+        let (path, format) = self.config_file(config_name.into())?;
+        let cfg = match &self.migrations {
+            Some(migrations) => {
+                load_path_migrated_format(&path, format, migrations, self.backups, self.create)?
+            }
+            None => self.load_file(&path, format)?,
+        };
+        self.write_back_defaults::<T>(&path, format)?;
+        Ok(cfg)
+    }
+
+    /// Load the configuration, taking missing fields from `Default`
+    ///
+    /// With [`ConfyBuilder::write_back_defaults`], the missing fields are
+    /// added to the file as well. For more information on errors and
+    /// behavior, see [`load_merged`]'s documentation.
+    ///
+    /// [`load_merged`]: fn.load_merged.html
+    pub fn load_merged<'a, T: Serialize + DeserializeOwned + Default>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+    ) -> Result<Merged<T>, ConfyError> {
+        let (path, format) = self.config_file(config_name.into())?;
+        let merged = load_path_merged_format(
+            &path,
+            format,
+            self.create,
+            self.migrations.as_ref(),
+            self.backups,
+        )?;
+        self.write_back_defaults::<T>(&path, format)?;
+        Ok(merged)
+    }
+
+    /// Add the fields missing from the file at `path`, if asked to.
+    fn write_back_defaults<T: Serialize + DeserializeOwned + Default>(
+        &self,
+        path: &Path,
+        format: Format,
+    ) -> Result<(), ConfyError> {
+        if self.write_back {
+            upgrade_path_format::<T>(path, format, self.backups)?;
+        }
+        Ok(())
+    }
+
+    /// Load the configuration from disk without ever creating it
+    ///
+    /// For more information on errors and behavior, see [`try_load`]'s
+    /// documentation.
+    ///
+    /// [`try_load`]: fn.try_load.html
+    pub fn try_load<'a, T: DeserializeOwned>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+    ) -> Result<Option<T>, ConfyError> {
+        let (path, format) = self.config_file(config_name.into())?;
+        try_read_path(&path)?
+            .map(|bytes| migrate::deserialize(&bytes, format, self.migrations.as_ref()))
+            .transpose()
+    }
+
+    fn load_file<T: Serialize + DeserializeOwned + Default>(
+        &self,
+        path: &Path,
+        format: Format,
+    ) -> Result<T, ConfyError> {
+        if self.create {
+            load_path_format(path, format)
+        } else {
+            try_load_path_format(path, format).map(Option::unwrap_or_default)
+        }
+    }
+
+    /// Save changes made to a configuration object
+    ///
+    /// If [`ConfyBuilder::backups`] was set, the previous versions of the file
+    /// are kept, see [`store_path_backups`]. With [`ConfyBuilder::migrations`],
+    /// the current version is recorded in the file. For more information on
+    /// errors and behavior, see [`store`]'s documentation.
+    ///
+    /// [`store_path_backups`]: fn.store_path_backups.html
+    /// [`store`]: fn.store.html
+    pub fn store<'a, T: Serialize>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+        cfg: T,
+    ) -> Result<(), ConfyError> {
+        let (path, format) = self.config_file(config_name.into())?;
+        let bytes = migrate::serialize(&cfg, format, self.migrations.as_ref())?;
+        store_bytes(&path, &bytes, None, self.backups)
+    }
+
+    /// Save changes made to a configuration object with the given file permissions
+    ///
+    /// For more information on errors and behavior, see [`store_perms`]'s
+    /// documentation.
+    ///
+    /// [`store_perms`]: fn.store_perms.html
+    pub fn store_perms<'a, T: Serialize>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+        cfg: T,
+        perms: Permissions,
+    ) -> Result<(), ConfyError> {
+        let (path, format) = self.config_file(config_name.into())?;
+        let bytes = migrate::serialize(&cfg, format, self.migrations.as_ref())?;
+        store_bytes(&path, &bytes, Some(perms), self.backups)
+    }
+
+    /// Roll a configuration file back to one of its backups
+    ///
+    /// For more information on errors and behavior, see [`restore_backup`]'s
+    /// documentation.
+    ///
+    /// [`restore_backup`]: fn.restore_backup.html
+    pub fn restore_backup<'a>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+        generation: usize,
+    ) -> Result<(), ConfyError> {
+        let path = self.get_configuration_file_path(config_name)?;
+        restore_backup(path, generation)
+    }
+
+    /// Change the configuration on disk under an exclusive lock
+    ///
+    /// For more information on errors and behavior, see [`update`]'s
+    /// documentation.
+    ///
+    /// [`update`]: fn.update.html
+    pub fn update<'a, T, F>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+        op: F,
+    ) -> Result<T, ConfyError>
+    where
+        T: Serialize + DeserializeOwned + Default,
+        F: FnOnce(&mut T),
+    {
+        let (path, format) = self.config_file(config_name.into())?;
+        update_path_format(&path, op, format, self.backups, self.migrations.as_ref())
+    }
+
+    /// Reload the configuration whenever its file changes
+    ///
+    /// For more information on errors and behavior, see [`watch`]'s
+    /// documentation.
+    ///
+    /// [`watch`]: fn.watch.html
+    #[cfg(feature = "watch")]
+    pub fn watch<'a, T, F>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+        callback: F,
+    ) -> Result<crate::Watcher, ConfyError>
+    where
+        T: DeserializeOwned + 'static,
+        F: FnMut(Result<T, ConfyError>) + Send + 'static,
+    {
+        let (path, format) = self.config_file(config_name.into())?;
+        crate::watch::watch_path_format(&path, format, self.migrations.clone(), callback)
+    }
+
+    /// Load the configuration and keep it in sync with its file
+    ///
+    /// The configuration is loaded like [`Confy::load`] does. Stores through
+    /// the returned handle keep the previous versions set with
+    /// [`ConfyBuilder::backups`]. For more information on errors and behavior,
+    /// see [`live`]'s documentation.
+    ///
+    /// [`live`]: fn.live.html
+    #[cfg(feature = "watch")]
+    pub fn live<'a, T>(
+        &self,
+        config_name: impl Into<Option<&'a str>>,
+    ) -> Result<crate::LiveConfig<T>, ConfyError>
+    where
+        T: Serialize + DeserializeOwned + Default + Send + Sync + 'static,
+    {
+        let config_name = config_name.into();
+        let (path, format) = self.config_file(config_name)?;
+        let cfg = self.load(config_name)?;
+        crate::LiveConfig::start(path, format, self.backups, self.migrations.clone(), cfg)
+    }
+
+    /// Load a file from the data directory, see [`Confy::data_path`]
+    ///
+    /// Behaves like [`Confy::load`], creating the file with default values if
+    /// it does not exist unless [`ConfyBuilder::create_missing`] is off.
+    pub fn load_data<'a, T: Serialize + DeserializeOwned + Default>(
+        &self,
+        name: impl Into<Option<&'a str>>,
+    ) -> Result<T, ConfyError> {
+        self.load_file(&self.data_path(name)?, self.format)
+    }
+
+    /// Store a file in the data directory, see [`Confy::data_path`]
+    pub fn store_data<'a, T: Serialize>(
+        &self,
+        name: impl Into<Option<&'a str>>,
+        cfg: T,
+    ) -> Result<(), ConfyError> {
+        store_path_format(self.data_path(name)?, cfg, self.format)
+    }
+
+    /// Load a file from the cache directory, see [`Confy::cache_path`]
+    ///
+    /// Behaves like [`Confy::load`], creating the file with default values if
+    /// it does not exist unless [`ConfyBuilder::create_missing`] is off.
+    pub fn load_cache<'a, T: Serialize + DeserializeOwned + Default>(
+        &self,
+        name: impl Into<Option<&'a str>>,
+    ) -> Result<T, ConfyError> {
+        self.load_file(&self.cache_path(name)?, self.format)
+    }
+
+    /// Store a file in the cache directory, see [`Confy::cache_path`]
+    pub fn store_cache<'a, T: Serialize>(
+        &self,
+        name: impl Into<Option<&'a str>>,
+        cfg: T,
+    ) -> Result<(), ConfyError> {
+        store_path_format(self.cache_path(name)?, cfg, self.format)
+    }
+
+    /// Load a file from the state directory, see [`Confy::state_path`]
+    ///
+    /// Behaves like [`Confy::load`], creating the file with default values if
+    /// it does not exist unless [`ConfyBuilder::create_missing`] is off.
+    pub fn load_state<'a, T: Serialize + DeserializeOwned + Default>(
+        &self,
+        name: impl Into<Option<&'a str>>,
+    ) -> Result<T, ConfyError> {
+        self.load_file(&self.state_path(name)?, self.format)
+    }
+
+    /// Store a file in the state directory, see [`Confy::state_path`]
+    pub fn store_state<'a, T: Serialize>(
+        &self,
+        name: impl Into<Option<&'a str>>,
+        cfg: T,
+    ) -> Result<(), ConfyError> {
+        store_path_format(self.state_path(name)?, cfg, self.format)
+    }
+}
+
+/// Builds a [`Confy`] handle.
+///
+/// Only the application name is required. The qualifier defaults to `rs` and
+/// the organization to none, as used by the free functions. The strategy
+/// defaults to [`ConfigStrategy::App`], regardless of
+/// [`change_config_strategy`], and the format to [`Format::default`].
+///
+/// [`change_config_strategy`]: fn.change_config_strategy.html
+#[derive(Debug, Clone, Default)]
+pub struct ConfyBuilder {
+    app_name: Option<String>,
+    qualifier: Option<String>,
+    organization: Option<String>,
+    strategy: Arc<ConfigStrategy>,
+    format: Option<Format>,
+    location_env: Option<String>,
+    backups: usize,
+    create_missing: Option<bool>,
+    migrations: Option<Migrations>,
+    write_back_defaults: bool,
+}
+
+impl ConfyBuilder {
+    /// Set the name of the application, which names its configuration directory.
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+
+    /// Set the qualifier of the application, usually the top-level domain of
+    /// the organization like `com` or `org`.
+    ///
+    /// With [`ConfigStrategy::Native`] on macOS, the qualifier, the
+    /// organization and the application name make up the bundle identifier
+    /// naming the configuration directory, like `com.acme-corp.my-app`. An
+    /// empty qualifier is left out.
+    pub fn qualifier(mut self, qualifier: impl Into<String>) -> Self {
+        self.qualifier = Some(qualifier.into());
+        self
+    }
+
+    /// Set the organization behind the application.
+    ///
+    /// Besides being part of the bundle identifier on macOS, see
+    /// [`ConfyBuilder::qualifier`], the configuration directory on Windows is
+    /// placed in a directory named after the organization.
+    pub fn organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Set the strategy used to place the configuration files.
+    pub fn strategy(mut self, strategy: ConfigStrategy) -> Self {
+        self.strategy = Arc::new(strategy);
+        self
+    }
+
+    /// Set the format of the configuration files.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Let environment variables starting with `prefix` relocate the configuration files.
+    ///
+    /// This is opt-in, so packagers and CI can move the configuration without
+    /// code changes: with the prefix `MYAPP`, `MYAPP_CONFIG` names the file of
+    /// the default configuration and `MYAPP_CONFIG_DIR` the directory of all
+    /// configuration files. See [`Confy::config_location`] for details. Only
+    /// handles built with this look at the variables, the free functions like
+    /// [`load`](crate::load) do not.
+    pub fn location_env(mut self, prefix: impl Into<String>) -> Self {
+        self.location_env = Some(prefix.into());
+        self
+    }
+
+    /// Keep up to `backups` previous versions of a configuration file when storing it.
+    ///
+    /// Defaults to none. See [`store_path_backups`] for how the backups are
+    /// named and [`Confy::restore_backup`] for rolling back to one of them.
+    ///
+    /// [`store_path_backups`]: fn.store_path_backups.html
+    pub fn backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Set whether loading a file that does not exist creates it with default values.
+    ///
+    /// Defaults to `true`. With `false`, [`Confy::load`] and the loads of
+    /// data, cache and state files return the default values without
+    /// writing anything, as needed on read-only file systems. Outdated files
+    /// are then only migrated in memory. Storing and updating still write
+    /// files.
+    pub fn create_missing(mut self, create_missing: bool) -> Self {
+        self.create_missing = Some(create_missing);
+        self
+    }
+
+    /// Upgrade configuration files written for older versions when loading them.
+    ///
+    /// The upgraded file is stored again, keeping the previous one if
+    /// [`ConfyBuilder::backups`] is set. Every other way the handle reads the
+    /// configuration file upgrades it as well, and every way it writes it
+    /// records the current version. Data, cache and state files are not
+    /// migrated. See [`Migrations`] for how versions are counted.
+    pub fn migrations(mut self, migrations: Migrations) -> Self {
+        self.migrations = Some(migrations);
+        self
+    }
+
+    /// Add fields missing from configuration files to them when loading.
+    ///
+    /// Defaults to `false`. When set, [`Confy::load`] and
+    /// [`Confy::load_merged`] store the default values of fields the file
+    /// lacks, typically ones added in a newer version of the program, so
+    /// users can discover them. Values already in the file are kept, see
+    /// [`upgrade_path`].
+    ///
+    /// [`upgrade_path`]: fn.upgrade_path.html
+    pub fn write_back_defaults(mut self, write_back_defaults: bool) -> Self {
+        self.write_back_defaults = write_back_defaults;
+        self
+    }
+
+    /// Create the [`Confy`] handle.
+    ///
+    /// Fails with [`ConfyError::MissingAppName`] if no application name was
+    /// set and with [`ConfyError::UnsupportedFormat`] if the feature of the
+    /// format is not enabled.
+    pub fn build(self) -> Result<Confy, ConfyError> {
+        let app_name = self
+            .app_name
+            .filter(|app_name| !app_name.is_empty())
+            .ok_or(ConfyError::MissingAppName)?;
+        let format = self.format.unwrap_or_default();
+        if !format.is_enabled() {
+            return Err(ConfyError::UnsupportedFormat(format));
+        }
+        Ok(Confy {
+            app_name,
+            qualifier: self
+                .qualifier
+                .unwrap_or_else(|| DEFAULT_QUALIFIER.to_string()),
+            organization: self.organization.unwrap_or_default(),
+            strategy: self.strategy,
+            format,
+            location_env: self.location_env,
+            backups: self.backups,
+            create: self.create_missing.unwrap_or(true),
+            migrations: self.migrations,
+            write_back: self.write_back_defaults,
+        })
+    }
+}
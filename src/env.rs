@@ -0,0 +1,115 @@
+//! Overriding configuration values from environment variables
+
+use crate::ConfyError;
+use crate::value::Lenient;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+
+/// Separates the field names of nested structs in a variable name.
+pub(crate) const SEPARATOR: &str = "__";
+
+/// Collect the environment variables which are valid UTF-8.
+pub(crate) fn vars() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+}
+
+/// Override the fields of `cfg` with all `vars` that start with `prefix`.
+///
+/// `<PREFIX>_SERVER__PORT=8080` sets the field `port` of the field `server`.
+/// Field names are matched ignoring case and the values are parsed into the
+/// type of the field they end up in. Variables naming no field of `cfg`,
+/// like `<PREFIX>_CONFIG`, are left alone.
+///
+/// Variables below a field without a value, like `<PREFIX>_TLS__CERT` for a
+/// `tls: Option<Tls>` that is `None`, make up a new value from their fields
+/// alone. If that is not enough, [`ConfyError::UnsetEnvironmentOverride`]
+/// names them.
+pub(crate) fn apply_overrides<T: Serialize + DeserializeOwned>(
+    cfg: T,
+    prefix: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<T, ConfyError> {
+    let prefix = format!("{prefix}_");
+    let overrides: Vec<(String, Vec<String>, String)> = vars
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let path = key.strip_prefix(&prefix)?;
+            let path: Vec<String> = path.split(SEPARATOR).map(str::to_string).collect();
+            if path.iter().any(String::is_empty) {
+                return None;
+            }
+            Some((key, path, value))
+        })
+        .collect();
+    if overrides.is_empty() {
+        return Ok(cfg);
+    }
+
+    let mut root = serde_json::to_value(&cfg).map_err(ConfyError::EnvironmentOverrideError)?;
+    let (unset, set): (Vec<_>, Vec<_>) = overrides
+        .into_iter()
+        .partition(|(_, path, _)| is_below_unset(&root, path));
+    for (_, path, value) in set {
+        if let Some(target) = field_mut(&mut root, &path, false) {
+            *target = Value::String(value);
+        }
+    }
+    if unset.is_empty() {
+        return T::deserialize(Lenient(root)).map_err(ConfyError::EnvironmentOverrideError);
+    }
+
+    // without them first, so that their error is not blamed on another variable
+    T::deserialize(Lenient(root.clone())).map_err(ConfyError::EnvironmentOverrideError)?;
+    let mut keys = Vec::new();
+    for (key, path, value) in unset {
+        if let Some(target) = field_mut(&mut root, &path, true) {
+            *target = Value::String(value);
+            keys.push(key);
+        }
+    }
+    keys.sort();
+    T::deserialize(Lenient(root))
+        .map_err(|e| ConfyError::UnsetEnvironmentOverride(keys.join(", "), e))
+}
+
+/// Whether `path` leads through a field without a value before its end.
+fn is_below_unset(value: &Value, path: &[String]) -> bool {
+    let mut target = value;
+    for segment in path {
+        let Value::Object(entries) = target else {
+            return target.is_null();
+        };
+        match entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(segment))
+        {
+            Some((_, value)) => target = value,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// The field at `path` below `value`, if the configuration has it.
+///
+/// With `create`, missing fields are added instead, for paths below a field
+/// without a value, whose fields do not show up in `value`.
+fn field_mut<'v>(value: &'v mut Value, path: &[String], create: bool) -> Option<&'v mut Value> {
+    let mut target = value;
+    for segment in path {
+        if create && target.is_null() {
+            *target = Value::Object(Map::new());
+        }
+        let Value::Object(entries) = target else {
+            return None;
+        };
+        let key = match entries.keys().find(|key| key.eq_ignore_ascii_case(segment)) {
+            Some(key) => key.clone(),
+            None if create => segment.to_lowercase(),
+            None => return None,
+        };
+        target = entries.entry(key).or_insert(Value::Null);
+    }
+    Some(target)
+}
@@ -0,0 +1,275 @@
+//! Runtime selection of the configuration file format
+
+use crate::ConfyError;
+use serde::{Serialize, de::DeserializeOwned};
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind::InvalidData};
+use std::path::Path;
+
+#[cfg(feature = "ini_conf")]
+mod ini;
+#[cfg(feature = "kdl_conf")]
+mod kdl;
+
+#[cfg(feature = "toml_conf")]
+use toml::{from_str as toml_from_str, to_string_pretty as toml_to_string_pretty};
+
+#[cfg(all(feature = "basic_toml_conf", not(feature = "toml_conf")))]
+use basic_toml::{from_str as toml_from_str, to_string as toml_to_string_pretty};
+
+/// The file formats `confy` knows how to read and write.
+///
+/// Which formats are actually available is decided by the cargo features
+/// that are enabled, see the [crate level documentation](crate#features).
+/// Using a format whose feature is disabled fails with
+/// [`ConfyError::UnsupportedFormat`].
+///
+/// When no format is given explicitly, `confy` uses [`Format::default`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Format {
+    /// [TOML](https://toml.io), requires either the `toml_conf` or the `basic_toml_conf` feature.
+    Toml,
+    /// [YAML](https://yaml.org), requires the `yaml_conf` feature.
+    Yaml,
+    /// [RON](https://docs.rs/ron), requires the `ron_conf` feature.
+    Ron,
+    /// [JSON](https://www.json.org), requires the `json_conf` feature.
+    Json,
+    /// [JSON5](https://json5.org), requires the `json5_conf` feature.
+    ///
+    /// Files may contain comments and trailing commas, which makes this
+    /// format a good fit for configurations that users edit by hand.
+    Json5,
+    /// INI, requires the `ini_conf` feature.
+    ///
+    /// Top-level fields go into the general section and fields holding a
+    /// struct become `[sections]`. Deeper nesting and sequences are not
+    /// supported.
+    Ini,
+    /// [KDL](https://kdl.dev), requires the `kdl_conf` feature.
+    Kdl,
+    /// [CBOR](https://cbor.io), a binary format, requires the `cbor_conf` feature.
+    Cbor,
+    /// [MessagePack](https://msgpack.org), a binary format, requires the `msgpack_conf` feature.
+    MessagePack,
+}
+
+impl Format {
+    /// The file extension used for configuration files in this format.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Format::Toml => "toml",
+            Format::Yaml => "yml",
+            Format::Ron => "ron",
+            Format::Json => "json",
+            Format::Json5 => "json5",
+            Format::Ini => "ini",
+            Format::Kdl => "kdl",
+            Format::Cbor => "cbor",
+            Format::MessagePack => "msgpack",
+        }
+    }
+
+    /// Look up the format belonging to a file extension, ignoring ASCII case.
+    ///
+    /// Besides the extension returned by [`Format::extension`], `yaml` is
+    /// recognised as well as `jsonc`, which is read as JSON5. Returns `None`
+    /// for unknown extensions, whether the format is enabled or not is not
+    /// checked.
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        [
+            ("toml", Format::Toml),
+            ("yml", Format::Yaml),
+            ("yaml", Format::Yaml),
+            ("ron", Format::Ron),
+            ("json", Format::Json),
+            ("json5", Format::Json5),
+            ("jsonc", Format::Json5),
+            ("ini", Format::Ini),
+            ("kdl", Format::Kdl),
+            ("cbor", Format::Cbor),
+            ("msgpack", Format::MessagePack),
+        ]
+        .into_iter()
+        .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+        .map(|(_, format)| format)
+    }
+
+    /// Determine the format of a configuration file from its extension.
+    ///
+    /// Paths without an extension use [`Format::default`]. Unknown extensions
+    /// fail with [`ConfyError::UnknownExtension`] and extensions of formats
+    /// which are not enabled with [`ConfyError::UnsupportedFormat`].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Format, ConfyError> {
+        let Some(extension) = path.as_ref().extension() else {
+            return Ok(Format::default());
+        };
+        let extension = extension.to_string_lossy();
+        match Format::from_extension(&extension) {
+            Some(format) if format.is_enabled() => Ok(format),
+            Some(format) => Err(ConfyError::UnsupportedFormat(format)),
+            None => Err(ConfyError::UnknownExtension(extension.into_owned())),
+        }
+    }
+
+    /// Whether support for this format was compiled in.
+    pub const fn is_enabled(self) -> bool {
+        match self {
+            Format::Toml => cfg!(any(feature = "toml_conf", feature = "basic_toml_conf")),
+            Format::Yaml => cfg!(feature = "yaml_conf"),
+            Format::Ron => cfg!(feature = "ron_conf"),
+            Format::Json => cfg!(feature = "json_conf"),
+            Format::Json5 => cfg!(feature = "json5_conf"),
+            Format::Ini => cfg!(feature = "ini_conf"),
+            Format::Kdl => cfg!(feature = "kdl_conf"),
+            Format::Cbor => cfg!(feature = "cbor_conf"),
+            Format::MessagePack => cfg!(feature = "msgpack_conf"),
+        }
+    }
+
+    /// Whether files in this format are binary rather than UTF-8 text.
+    pub const fn is_binary(self) -> bool {
+        matches!(self, Format::Cbor | Format::MessagePack)
+    }
+
+    pub(crate) fn deserialize<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, ConfyError> {
+        match self {
+            #[cfg(feature = "cbor_conf")]
+            Format::Cbor => ciborium::from_reader(bytes).map_err(ConfyError::BadCborData),
+            #[cfg(feature = "msgpack_conf")]
+            Format::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(ConfyError::BadMessagePackData)
+            }
+            _ => {
+                let s = std::str::from_utf8(bytes).map_err(|e| {
+                    ConfyError::ReadConfigurationFileError(IoError::new(InvalidData, e))
+                })?;
+                self.deserialize_str(s)
+            }
+        }
+    }
+
+    // unused arguments when only binary formats are enabled
+    #[allow(unused_variables)]
+    fn deserialize_str<T: DeserializeOwned>(self, s: &str) -> Result<T, ConfyError> {
+        match self {
+            #[cfg(any(feature = "toml_conf", feature = "basic_toml_conf"))]
+            Format::Toml => toml_from_str(s).map_err(ConfyError::BadTomlData),
+            #[cfg(feature = "yaml_conf")]
+            Format::Yaml => serde_yaml::from_str(s).map_err(ConfyError::BadYamlData),
+            #[cfg(feature = "ron_conf")]
+            Format::Ron => ron::from_str(s).map_err(ConfyError::BadRonData),
+            #[cfg(feature = "json_conf")]
+            Format::Json => serde_json::from_str(s).map_err(ConfyError::BadJsonData),
+            #[cfg(feature = "json5_conf")]
+            Format::Json5 => json5::from_str(s).map_err(ConfyError::BadJson5Data),
+            #[cfg(feature = "ini_conf")]
+            Format::Ini => ini::from_str(s),
+            #[cfg(feature = "kdl_conf")]
+            Format::Kdl => kdl::from_str(s).map_err(ConfyError::BadKdlData),
+            #[allow(unreachable_patterns)]
+            _ => Err(ConfyError::UnsupportedFormat(self)),
+        }
+    }
+
+    /// Whether structs are written unlike maps, as in RON, where a struct is
+    /// `(port: 80)` but a map `{"port": 80}`.
+    ///
+    /// Untyped contents, like a migrated [`serde_json::Value`], are written as
+    /// maps, which do not load into the configuration struct again. Files in
+    /// these formats are written from the struct instead.
+    pub(crate) const fn has_typed_structs(self) -> bool {
+        matches!(self, Format::Ron)
+    }
+
+    /// `cfg` as it is read back after being written in this format, which
+    /// leaves out what the format cannot represent, like `None` in TOML.
+    pub(crate) fn round_trip<T: Serialize, U: DeserializeOwned>(
+        self,
+        cfg: &T,
+    ) -> Result<U, ConfyError> {
+        self.deserialize(&self.serialize(cfg)?)
+    }
+
+    pub(crate) fn serialize<T: Serialize>(self, cfg: &T) -> Result<Vec<u8>, ConfyError> {
+        match self {
+            #[cfg(feature = "cbor_conf")]
+            Format::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(cfg, &mut buf).map_err(ConfyError::SerializeCborError)?;
+                Ok(buf)
+            }
+            #[cfg(feature = "msgpack_conf")]
+            Format::MessagePack => {
+                rmp_serde::to_vec_named(cfg).map_err(ConfyError::SerializeMessagePackError)
+            }
+            _ => self.serialize_string(cfg).map(String::into_bytes),
+        }
+    }
+
+    // unused arguments when only binary formats are enabled
+    #[allow(unused_variables)]
+    fn serialize_string<T: Serialize>(self, cfg: &T) -> Result<String, ConfyError> {
+        match self {
+            #[cfg(any(feature = "toml_conf", feature = "basic_toml_conf"))]
+            Format::Toml => toml_to_string_pretty(cfg).map_err(ConfyError::SerializeTomlError),
+            #[cfg(feature = "yaml_conf")]
+            Format::Yaml => serde_yaml::to_string(cfg).map_err(ConfyError::SerializeYamlError),
+            #[cfg(feature = "ron_conf")]
+            Format::Ron => {
+                let pretty_cfg = ron::ser::PrettyConfig::default();
+                ron::ser::to_string_pretty(cfg, pretty_cfg).map_err(ConfyError::SerializeRonError)
+            }
+            #[cfg(feature = "json_conf")]
+            Format::Json => {
+                serde_json::to_string_pretty(cfg).map_err(ConfyError::SerializeJsonError)
+            }
+            #[cfg(feature = "json5_conf")]
+            Format::Json5 => json5::to_string(cfg).map_err(ConfyError::SerializeJson5Error),
+            #[cfg(feature = "ini_conf")]
+            Format::Ini => ini::to_string(cfg),
+            #[cfg(feature = "kdl_conf")]
+            Format::Kdl => kdl::to_string(cfg).map_err(ConfyError::SerializeKdlError),
+            #[allow(unreachable_patterns)]
+            _ => Err(ConfyError::UnsupportedFormat(self)),
+        }
+    }
+}
+
+/// The default format is the first enabled one out of TOML, YAML, RON, JSON, JSON5, INI,
+/// KDL, CBOR and MessagePack.
+impl Default for Format {
+    fn default() -> Self {
+        [
+            Format::Toml,
+            Format::Yaml,
+            Format::Ron,
+            Format::Json,
+            Format::Json5,
+            Format::Ini,
+            Format::Kdl,
+            Format::Cbor,
+            Format::MessagePack,
+        ]
+        .into_iter()
+        .find(|format| format.is_enabled())
+        .unwrap_or(Format::Toml)
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Toml => "TOML",
+            Format::Yaml => "YAML",
+            Format::Ron => "RON",
+            Format::Json => "JSON",
+            Format::Json5 => "JSON5",
+            Format::Ini => "INI",
+            Format::Kdl => "KDL",
+            Format::Cbor => "CBOR",
+            Format::MessagePack => "MessagePack",
+        })
+    }
+}
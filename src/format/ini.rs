@@ -0,0 +1,94 @@
+//! INI support, mapping one level of struct nesting to sections
+//!
+//! Top-level scalar fields end up in the general section at the start of the
+//! file, while fields holding structs or maps become `[sections]`. Anything
+//! nested deeper than that, as well as sequences, cannot be expressed in INI.
+
+use crate::ConfyError;
+use crate::value::Lenient;
+use ini::Ini;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Error, Map, Value};
+use std::io::{Error as IoError, ErrorKind::InvalidData};
+
+pub(crate) fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, ConfyError> {
+    let ini = Ini::load_from_str(s).map_err(ConfyError::BadIniData)?;
+    to_config(&ini).map_err(ConfyError::IniValueError)
+}
+
+/// Map the sections of `ini` onto the fields of the configuration.
+fn to_config<T: DeserializeOwned>(ini: &Ini) -> Result<T, Error> {
+    let mut root = Map::new();
+    for (section, properties) in ini {
+        let entries = match section {
+            None => &mut root,
+            Some(name) => match root
+                .entry(name)
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(entries) => entries,
+                _ => {
+                    return Err(serde::de::Error::custom(format_args!(
+                        "`{name}` is used both as a key and as a section"
+                    )));
+                }
+            },
+        };
+        for (key, value) in properties.iter() {
+            entries.insert(key.to_string(), Value::String(value.to_string()));
+        }
+    }
+
+    T::deserialize(Lenient(Value::Object(root)))
+}
+
+pub(crate) fn to_string<T: Serialize>(cfg: &T) -> Result<String, ConfyError> {
+    let ini = to_ini(cfg).map_err(ConfyError::IniValueError)?;
+    let mut buf = Vec::new();
+    ini.write_to(&mut buf)
+        .map_err(ConfyError::SerializeIniError)?;
+    String::from_utf8(buf).map_err(|e| ConfyError::SerializeIniError(IoError::new(InvalidData, e)))
+}
+
+/// Map the fields of the configuration onto sections.
+fn to_ini<T: Serialize>(cfg: &T) -> Result<Ini, Error> {
+    let Value::Object(root) = serde_json::to_value(cfg)? else {
+        return Err(serde::ser::Error::custom(
+            "only structs and maps can be stored as INI",
+        ));
+    };
+
+    let mut ini = Ini::new();
+    for (key, value) in root {
+        match value {
+            Value::Object(entries) => {
+                ini.entry(Some(key.clone())).or_insert(Default::default());
+                for (name, value) in entries {
+                    if let Some(value) = scalar(value, &name)? {
+                        ini.with_section(Some(key.as_str())).set(name, value);
+                    }
+                }
+            }
+            value => {
+                if let Some(value) = scalar(value, &key)? {
+                    ini.with_general_section().set(key, value);
+                }
+            }
+        }
+    }
+
+    Ok(ini)
+}
+
+/// Render a single INI value, `None` values are left out of the file.
+fn scalar(value: Value, key: &str) -> Result<Option<String>, Error> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Ok(Some(s)),
+        Value::Bool(b) => Ok(Some(b.to_string())),
+        Value::Number(n) => Ok(Some(n.to_string())),
+        Value::Array(_) | Value::Object(_) => Err(serde::ser::Error::custom(format_args!(
+            "`{key}` is nested too deeply to be stored as INI"
+        ))),
+    }
+}
@@ -0,0 +1,64 @@
+//! KDL support
+//!
+//! Parsing goes straight through the serde support of the `kdl` crate. Its
+//! serializer leaves out `false` and `None` fields though, which then fail to
+//! load again, so documents are built from a [`Value`] here instead. Every
+//! field becomes a node named after it, holding the value as its argument,
+//! its children for nested structs and `-` children for sequences.
+
+use ::kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue, se::Error};
+use serde::{Serialize, de::DeserializeOwned, ser::Error as _};
+use serde_json::Value;
+
+pub(crate) fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, ::kdl::de::Error> {
+    ::kdl::de::from_str(s)
+}
+
+pub(crate) fn to_string<T: Serialize>(cfg: &T) -> Result<String, Error> {
+    let mut doc = match serde_json::to_value(cfg).map_err(Error::custom)? {
+        Value::Object(entries) => document(entries),
+        _ => return Err(Error::custom("only structs and maps can be stored as KDL")),
+    };
+    doc.autoformat();
+    Ok(doc.to_string())
+}
+
+fn document(entries: impl IntoIterator<Item = (String, Value)>) -> KdlDocument {
+    let mut doc = KdlDocument::new();
+    doc.nodes_mut()
+        .extend(entries.into_iter().map(|(name, value)| node(name, value)));
+    doc
+}
+
+fn node(name: String, value: Value) -> KdlNode {
+    let mut node = KdlNode::new(name);
+    match value {
+        Value::Object(entries) => node.set_children(document(entries)),
+        Value::Array(values) if !values.is_empty() && values.iter().all(is_scalar) => {
+            node.entries_mut()
+                .extend(values.into_iter().map(|value| KdlEntry::new(scalar(value))));
+        }
+        Value::Array(values) => node.set_children(document(
+            values.into_iter().map(|value| ("-".to_string(), value)),
+        )),
+        value => node.entries_mut().push(KdlEntry::new(scalar(value))),
+    }
+    node
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+fn scalar(value: Value) -> KdlValue {
+    match value {
+        Value::String(s) => KdlValue::String(s),
+        Value::Bool(b) => KdlValue::Bool(b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => KdlValue::Integer(i.into()),
+            (_, Some(u)) => KdlValue::Integer(u.into()),
+            _ => KdlValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        _ => KdlValue::Null,
+    }
+}
@@ -0,0 +1,237 @@
+//! Merging system, user and project configuration files
+
+use crate::value::{Lenient, merge};
+use crate::{ConfyError, Format, get_configuration_file_path};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A source of configuration values for [`load_layered`].
+///
+/// [`load_layered`]: fn.load_layered.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layer {
+    /// The `Default` implementation of the configuration, used for every
+    /// value that no file sets.
+    Default,
+    /// A system wide file below one of the directories in `$XDG_CONFIG_DIRS`.
+    System(PathBuf),
+    /// The file of the user, as returned by [`get_configuration_file_path`].
+    ///
+    /// [`get_configuration_file_path`]: fn.get_configuration_file_path.html
+    User(PathBuf),
+    /// A file in the current directory, for settings specific to one project.
+    Project(PathBuf),
+}
+
+impl Layer {
+    /// The file this layer is read from, `None` for [`Layer::Default`].
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Layer::Default => None,
+            Layer::System(path) | Layer::User(path) | Layer::Project(path) => Some(path),
+        }
+    }
+}
+
+/// A configuration merged from several [`Layer`]s, see [`load_layered`].
+///
+/// Dereferences to the configuration itself.
+///
+/// [`load_layered`]: fn.load_layered.html
+#[derive(Debug, Clone)]
+pub struct Layered<T> {
+    config: T,
+    sources: BTreeMap<String, Layer>,
+}
+
+impl<T> Layered<T> {
+    /// The layer which supplied the value at `key`.
+    ///
+    /// Nested fields are addressed with dots, like `server.port`. For fields
+    /// holding a struct, this is the last layer that set any of its fields.
+    pub fn source(&self, key: &str) -> Option<&Layer> {
+        self.sources.get(key)
+    }
+
+    /// All keys of the configuration together with the layer that supplied them.
+    pub fn sources(&self) -> impl Iterator<Item = (&str, &Layer)> {
+        self.sources
+            .iter()
+            .map(|(key, layer)| (key.as_str(), layer))
+    }
+
+    /// Take the merged configuration.
+    pub fn into_inner(self) -> T {
+        self.config
+    }
+}
+
+impl<T> Deref for Layered<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.config
+    }
+}
+
+/// The layers [`load_layered`] reads, from lowest to highest priority.
+///
+/// These are [`Layer::Default`], a [`Layer::System`] file for every directory
+/// in `$XDG_CONFIG_DIRS` (`/etc/xdg` if unset on Unix), the [`Layer::User`]
+/// file and a [`Layer::Project`] file named `.<app_name>.<extension>` in the
+/// current directory. If `config_name` is given, the system and user files
+/// are named after it like for [`load`], and the project file becomes
+/// `.<app_name>.<config_name>.<extension>`.
+///
+/// The files do not need to exist.
+///
+/// [`load_layered`]: fn.load_layered.html
+/// [`load`]: fn.load.html
+pub fn layer_paths<'a>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+) -> Result<Vec<Layer>, ConfyError> {
+    let config_name = config_name.into();
+    let extension = Format::default().extension();
+    let file_name = format!("{}.{extension}", config_name.unwrap_or("default-config"));
+
+    let mut layers = vec![Layer::Default];
+
+    // the first directory is the most important one, so it goes last
+    let system_dirs = std::env::var_os("XDG_CONFIG_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .or_else(|| cfg!(unix).then(|| OsString::from("/etc/xdg")));
+    if let Some(dirs) = system_dirs {
+        let dirs: Vec<PathBuf> = std::env::split_paths(&dirs)
+            .filter(|dir| dir.is_absolute())
+            .collect();
+        layers.extend(
+            dirs.into_iter()
+                .rev()
+                .map(|dir| Layer::System(dir.join(app_name).join(&file_name))),
+        );
+    }
+
+    layers.push(Layer::User(get_configuration_file_path(
+        app_name,
+        config_name,
+    )?));
+
+    let project_dir = std::env::current_dir().map_err(|e| {
+        ConfyError::BadConfigDirectory(format!("could not determine current directory: {e}"))
+    })?;
+    let project_file = match config_name {
+        Some(config_name) => format!(".{app_name}.{config_name}.{extension}"),
+        None => format!(".{app_name}.{extension}"),
+    };
+    layers.push(Layer::Project(project_dir.join(project_file)));
+
+    Ok(layers)
+}
+
+/// Load an application configuration merged from system, user and project files
+///
+/// All files returned by [`layer_paths`] that exist are deep-merged on top of
+/// the `Default` implementation of the configuration, in order of priority:
+/// a field set in a project file wins over the same field in the user's file,
+/// which in turn wins over the system wide files. Fields of nested structs are
+/// merged one by one, so every file only needs to contain the values it changes.
+///
+/// Unlike [`load`], no files are created. [`Layered::source`] tells which
+/// layer supplied a value.
+///
+/// ```rust,no_run
+/// # use confy::{ConfyError, Layer};
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     theme: String,
+/// }
+///
+/// let cfg = confy::load_layered::<MyConfig>("my-app-name", None)?;
+/// if let Some(Layer::Project(path)) = cfg.source("theme") {
+///     println!("theme {} is set by {}", cfg.theme, path.display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`layer_paths`]: fn.layer_paths.html
+/// [`load`]: fn.load.html
+pub fn load_layered<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+) -> Result<Layered<T>, ConfyError> {
+    load_layers(layer_paths(app_name, config_name)?)
+}
+
+/// Load a configuration merged from the given layers
+///
+/// This is an alternate version of [`load_layered`] that allows the specification of
+/// arbitrary layers instead of the system ones. Later layers take priority over
+/// earlier ones, missing and empty files are skipped and the format of every
+/// file is determined from its extension. For more information on errors and
+/// behavior, see [`load_layered`]'s documentation.
+///
+/// [`load_layered`]: fn.load_layered.html
+pub fn load_layers<T: Serialize + DeserializeOwned + Default>(
+    layers: impl IntoIterator<Item = Layer>,
+) -> Result<Layered<T>, ConfyError> {
+    let mut merged = Value::Null;
+    let mut sources = BTreeMap::new();
+
+    for layer in layers {
+        let value = match layer.path() {
+            None => serde_json::to_value(T::default()).map_err(ConfyError::LayerMergeError)?,
+            Some(path) => {
+                let format = Format::from_path(path)?;
+                match fs::read(path) {
+                    Ok(bytes) => format.deserialize(&bytes)?,
+                    Err(ref e) if e.kind() == NotFound => continue,
+                    Err(e) => return Err(ConfyError::GeneralLoadError(e)),
+                }
+            }
+        };
+        // empty files, like an empty YAML file, hold nothing to merge
+        if value.is_null() {
+            continue;
+        }
+        record_sources(&value, &mut String::new(), &layer, &mut sources);
+        merge(&mut merged, value);
+    }
+
+    let config = T::deserialize(Lenient(merged)).map_err(ConfyError::LayerMergeError)?;
+    Ok(Layered { config, sources })
+}
+
+/// Note `layer` as the source of every key in `value`.
+fn record_sources(
+    value: &Value,
+    key: &mut String,
+    layer: &Layer,
+    sources: &mut BTreeMap<String, Layer>,
+) {
+    let Value::Object(entries) = value else {
+        // a plain value replaces everything that was nested below it
+        let nested = format!("{key}.");
+        sources.retain(|existing, _| !existing.starts_with(&nested));
+        return;
+    };
+    for (name, value) in entries {
+        let len = key.len();
+        if !key.is_empty() {
+            key.push('.');
+        }
+        key.push_str(name);
+        sources.insert(key.clone(), layer.clone());
+        record_sources(value, key, layer, sources);
+        key.truncate(len);
+    }
+}
@@ -69,7 +69,13 @@
 //!
 //! ## Features
 //!
-//! Exactly **one** of the features has to be enabled from the following table.
+//! At least **one** of the features has to be enabled from the following table.
+//! Enabling several of them is fine, the [`Format`] used for a file is then picked at
+//! runtime: [`load_path`] and [`store_path`] look at the file extension (see
+//! [`Format::from_path`]), while [`load_path_format`] and [`store_path_format`] take the
+//! format explicitly. Configuration files in the system directories, as used by [`load`]
+//! and [`store`], use [`Format::default`], which is the first enabled format out of TOML,
+//! YAML, RON, JSON, JSON5, INI, KDL, CBOR and MessagePack.
 //!
 //! ### Tip
 //! to add this crate to your project with the default, toml config do the following: `cargo add confy`, otherwise do something like: `cargo add confy --no-default-features --features yaml_conf`, for more info, see [cargo docs on features]
@@ -81,7 +87,13 @@
 //! **default**: `toml_conf` | [toml] | considered a reasonable default, uses the standard-compliant [`toml` crate]
 //! `yaml_conf` | [yaml] | uses the [`serde_yaml` crate]
 //! `ron_conf` | [ron] | Rusty Object Notation, uses the [`ron` crate]
-//! `basic_toml_conf` | [toml] | alternative to the default `toml_conf`, instead of using the [`toml` crate], the [`basic_toml` crate] is used, in order to cut down on the number of dependencies, speed up compilation and shrink binary size. **_DISCLAIMER_**: this crate is **not** standard compliant, **nor** maintained, otherwise should work fine in most situations.
+//! `json_conf` | [json] | uses the [`serde_json` crate], files are written pretty-printed. The crate is compiled in without the feature as well, for environment overrides, layers and migrations, so the feature only enables the format
+//! `json5_conf` | [json5] | JSON with comments and trailing commas, uses the [`json5` crate]. Files ending in `.jsonc` are read with it as well
+//! `ini_conf` | INI | uses the [`rust-ini` crate], structs nested one level deep become sections
+//! `kdl_conf` | [kdl] | uses the [`kdl` crate], every field becomes a node named after it
+//! `cbor_conf` | [cbor] | binary, uses the [`ciborium` crate], meant for machine-owned state rather than hand-edited files
+//! `msgpack_conf` | [msgpack] | binary, uses the [`rmp-serde` crate], meant for machine-owned state rather than hand-edited files
+//! `basic_toml_conf` | [toml] | alternative to the default `toml_conf`, instead of using the [`toml` crate], the [`basic_toml` crate] is used, in order to cut down on the number of dependencies, speed up compilation and shrink binary size. If both are enabled, the [`toml` crate] wins. **_DISCLAIMER_**: this crate is **not** standard compliant, **nor** maintained, otherwise should work fine in most situations.
 //!
 //! [toml]: https://toml.io
 //! [`toml` crate]: https://docs.rs/toml
@@ -89,78 +101,102 @@
 //! [`serde_yaml` crate]: https://docs.rs/serde_yaml
 //! [ron]: https://docs.rs/ron
 //! [`ron` crate]: https://docs.rs/ron
+//! [json]: https://www.json.org
+//! [`serde_json` crate]: https://docs.rs/serde_json
+//! [json5]: https://json5.org
+//! [`json5` crate]: https://docs.rs/json5
+//! [`rust-ini` crate]: https://docs.rs/rust-ini
+//! [kdl]: https://kdl.dev
+//! [`kdl` crate]: https://docs.rs/kdl
+//! [cbor]: https://cbor.io
+//! [`ciborium` crate]: https://docs.rs/ciborium
+//! [msgpack]: https://msgpack.org
+//! [`rmp-serde` crate]: https://docs.rs/rmp-serde
 //! [`basic_toml` crate]: https://docs.rs/basic_toml
-
+//!
+//! Independent of the format, the `watch` feature adds `watch` and `watch_path` for reloading
+//! a configuration whenever its file changes, using the [`notify` crate], as well as
+//! `LiveConfig`, a handle sharing the current configuration between threads.
+//!
+//! [`notify` crate]: https://docs.rs/notify
+
+mod backup;
+mod builder;
+mod env;
+mod format;
+mod layered;
+#[cfg(feature = "watch")]
+mod live;
+mod location;
+mod locator;
+mod lock;
+mod merged;
+mod migrate;
+mod portable;
+mod quarantine;
 mod utils;
+mod value;
+#[cfg(feature = "watch")]
+mod watch;
 use etcetera::app_strategy;
+use lock::FileLock;
+use portable::Portable;
 use utils::*;
 
+pub use backup::{backup_path, restore_backup};
+pub use builder::{Confy, ConfyBuilder};
+pub use format::Format;
+pub use layered::{Layer, Layered, layer_paths, load_layered, load_layers};
+#[cfg(feature = "watch")]
+pub use live::{LiveConfig, live, live_path};
+pub use location::{ConfigLocation, LocationSource};
+pub use locator::ConfigLocator;
+pub use merged::{Merged, load_merged, load_path_merged, upgrade_path};
+pub use migrate::{Migrations, load_migrated, load_path_migrated};
+pub use quarantine::Quarantined;
+#[cfg(feature = "watch")]
+pub use watch::{Watcher, watch, watch_path};
+
 use etcetera::{
     AppStrategy, AppStrategyArgs, app_strategy::choose_app_strategy,
     app_strategy::choose_native_strategy,
 };
 use lazy_static::lazy_static;
 use serde::{Serialize, de::DeserializeOwned};
-use std::fs::{self, File, OpenOptions, Permissions};
-use std::io::{ErrorKind::NotFound, Write};
+use std::fmt;
+use std::fs::{self, File, Permissions};
+use std::io::ErrorKind::NotFound;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
 #[cfg(feature = "toml_conf")]
-use toml::{
-    de::Error as TomlDeErr, from_str as toml_from_str, ser::Error as TomlSerErr,
-    to_string_pretty as toml_to_string_pretty,
-};
+use toml::{de::Error as TomlDeErr, ser::Error as TomlSerErr};
 
-#[cfg(feature = "basic_toml_conf")]
-use basic_toml::{
-    Error as TomlDeErr, Error as TomlSerErr, from_str as toml_from_str,
-    to_string as toml_to_string_pretty,
-};
+#[cfg(all(feature = "basic_toml_conf", not(feature = "toml_conf")))]
+use basic_toml::{Error as TomlDeErr, Error as TomlSerErr};
 
 #[cfg(not(any(
     feature = "toml_conf",
     feature = "basic_toml_conf",
     feature = "yaml_conf",
-    feature = "ron_conf"
+    feature = "ron_conf",
+    feature = "json_conf",
+    feature = "json5_conf",
+    feature = "ini_conf",
+    feature = "kdl_conf",
+    feature = "cbor_conf",
+    feature = "msgpack_conf"
 )))]
 compile_error!(
-    "Exactly one config language feature must be enabled to use \
-confy. Please enable one of either the `toml_conf`, `yaml_conf`, \
-, `ron_conf` or `toml_basic_conf` features."
-);
-
-#[cfg(any(
-    all(feature = "toml_conf", feature = "basic_toml_conf"),
-    all(
-        any(feature = "toml_conf", feature = "basic_toml_conf"),
-        feature = "yaml_conf"
-    ),
-    all(
-        any(feature = "toml_conf", feature = "basic_toml_conf"),
-        feature = "ron_conf"
-    ),
-    all(feature = "ron_conf", feature = "yaml_conf"),
-))]
-compile_error!(
-    "Exactly one config language feature must be enabled to compile \
-confy.  Please disable one of either the `toml_conf`, `basic_toml_conf`, `yaml_conf`, or `ron_conf` features. \
-NOTE: `toml_conf` is a default feature, so disabling it might mean switching off \
-default features for confy in your Cargo.toml"
+    "At least one config language feature must be enabled to use \
+confy. Please enable one or more of the `toml_conf`, `basic_toml_conf`, \
+`yaml_conf`, `ron_conf`, `json_conf`, `json5_conf`, `ini_conf`, `kdl_conf`, \
+`cbor_conf` or `msgpack_conf` features."
 );
 
-#[cfg(any(feature = "toml_conf", feature = "basic_toml_conf"))]
-const EXTENSION: &str = "toml";
-
-#[cfg(feature = "yaml_conf")]
-const EXTENSION: &str = "yml";
-
-#[cfg(feature = "ron_conf")]
-const EXTENSION: &str = "ron";
-
 lazy_static! {
-    static ref STRATEGY: Mutex<ConfigStrategy> = Mutex::new(ConfigStrategy::App);
+    static ref STRATEGY: Mutex<Arc<ConfigStrategy>> = Mutex::new(Arc::new(ConfigStrategy::App));
 }
 
 /// The errors the confy crate can encounter.
@@ -178,6 +214,30 @@ pub enum ConfyError {
     #[error("Bad RON data")]
     BadRonData(#[source] ron::error::SpannedError),
 
+    #[cfg(feature = "json_conf")]
+    #[error("Bad JSON data")]
+    BadJsonData(#[source] serde_json::Error),
+
+    #[cfg(feature = "json5_conf")]
+    #[error("Bad JSON5 data")]
+    BadJson5Data(#[source] json5::Error),
+
+    #[cfg(feature = "ini_conf")]
+    #[error("Bad INI data")]
+    BadIniData(#[source] ini::ParseError),
+
+    #[cfg(feature = "kdl_conf")]
+    #[error("Bad KDL data")]
+    BadKdlData(#[source] kdl::de::Error),
+
+    #[cfg(feature = "cbor_conf")]
+    #[error("Bad CBOR data")]
+    BadCborData(#[source] ciborium::de::Error<std::io::Error>),
+
+    #[cfg(feature = "msgpack_conf")]
+    #[error("Bad MessagePack data")]
+    BadMessagePackData(#[source] rmp_serde::decode::Error),
+
     #[error("Failed to create directory")]
     DirectoryCreationFailed(#[source] std::io::Error),
 
@@ -199,6 +259,34 @@ pub enum ConfyError {
     #[error("Failed to serialize configuration data into RON")]
     SerializeRonError(#[source] ron::error::Error),
 
+    #[cfg(feature = "json_conf")]
+    #[error("Failed to serialize configuration data into JSON")]
+    SerializeJsonError(#[source] serde_json::Error),
+
+    #[cfg(feature = "json5_conf")]
+    #[error("Failed to serialize configuration data into JSON5")]
+    SerializeJson5Error(#[source] json5::Error),
+
+    #[cfg(feature = "ini_conf")]
+    #[error("Failed to serialize configuration data into INI")]
+    SerializeIniError(#[source] std::io::Error),
+
+    #[cfg(feature = "ini_conf")]
+    #[error("The configuration data does not fit the sections of INI")]
+    IniValueError(#[source] serde_json::Error),
+
+    #[cfg(feature = "kdl_conf")]
+    #[error("Failed to serialize configuration data into KDL")]
+    SerializeKdlError(#[source] kdl::se::Error),
+
+    #[cfg(feature = "cbor_conf")]
+    #[error("Failed to serialize configuration data into CBOR")]
+    SerializeCborError(#[source] ciborium::ser::Error<std::io::Error>),
+
+    #[cfg(feature = "msgpack_conf")]
+    #[error("Failed to serialize configuration data into MessagePack")]
+    SerializeMessagePackError(#[source] rmp_serde::encode::Error),
+
     #[error("Failed to write configuration file")]
     WriteConfigurationFileError(#[source] std::io::Error),
 
@@ -210,26 +298,106 @@ pub enum ConfyError {
 
     #[error("Failed to set configuration file permissions")]
     SetPermissionsFileError(#[source] std::io::Error),
+
+    #[error("Support for the {0} format is not enabled, please enable its cargo feature")]
+    UnsupportedFormat(Format),
+
+    #[error("Unknown configuration file extension: {0:?}")]
+    UnknownExtension(String),
+
+    #[error("Failed to apply environment variable overrides")]
+    EnvironmentOverrideError(#[source] serde_json::Error),
+
+    #[error("The environment variables {0} set fields of a value the configuration does not have")]
+    UnsetEnvironmentOverride(String, #[source] serde_json::Error),
+
+    #[error("Failed to merge configuration layers")]
+    LayerMergeError(#[source] serde_json::Error),
+
+    #[error("No application name was given")]
+    MissingAppName,
+
+    #[error("Failed to lock configuration file")]
+    LockFileError(#[source] std::io::Error),
+
+    #[error("Timed out waiting for the lock on {0:?}")]
+    LockTimeout(PathBuf),
+
+    #[error("Failed to back up configuration file")]
+    BackupError(#[source] std::io::Error),
+
+    #[error("No backup found at {0:?}")]
+    BackupNotFound(PathBuf),
+
+    #[error("Failed to move the broken configuration file aside")]
+    QuarantineError(#[source] std::io::Error),
+
+    #[error("Failed to merge the configuration over its default values")]
+    DefaultMergeError(#[source] serde_json::Error),
+
+    #[error("Failed to deserialize the migrated configuration")]
+    MigrationError(#[source] serde_json::Error),
+
+    #[error("Configuration version {0} is newer than the supported version {1}")]
+    VersionTooNew(u64, u64),
+
+    #[error("Bad configuration version: {0}")]
+    BadVersion(String),
+
+    #[error("The configuration has no field {0:?} to record its version in")]
+    VersionFieldMissing(String),
+
+    #[cfg(feature = "watch")]
+    #[error("Failed to watch configuration file")]
+    WatchError(#[source] notify::Error),
 }
 
 /// Determine what strategy `confy` should use
 /// these are based off of [the etcetera crate's strategies](https://docs.rs/etcetera/latest/etcetera/#strategies).
 ///
-/// To change use [`change_config_strategy`] function before calling any load or save functions.
+/// To change use [`change_config_strategy`] function before calling any load or save functions,
+/// or pass it to [`ConfyBuilder::strategy`].
+#[derive(Default)]
 pub enum ConfigStrategy {
     /// The `App` Strategy is the default strategy
     /// this is the traditional XDG strategy and will place the config file in the XDG directories.
     /// See [Etcetera App Strategy](https://docs.rs/etcetera/latest/etcetera/#appstrategy) for more information.
+    #[default]
     App,
     /// The `Native` Strategy is mainly used for GUI applications and places the config directory based on the
     /// host systems determination. See [Etcetera Native Strategy](https://docs.rs/etcetera/latest/etcetera/#native-strategy) for more information.
     Native,
+    /// The `Portable` Strategy keeps everything next to the executable, for self-contained deployments
+    /// like on a USB stick. Configuration files go into `config/<app_name>` beside the executable, data,
+    /// cache and state files into `data/<app_name>`, `cache/<app_name>` and `state/<app_name>`.
+    ///
+    /// The `App` and `Native` strategies switch to this one as well if a marker file named
+    /// `<app_name>.portable` is present in the directory of the executable.
+    Portable,
+    /// The `Custom` Strategy asks a user supplied [`ConfigLocator`] for the directories, for example to
+    /// place configuration files under a centrally managed root.
+    Custom(Box<dyn ConfigLocator>),
+}
+
+impl fmt::Debug for ConfigStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigStrategy::App => f.write_str("App"),
+            ConfigStrategy::Native => f.write_str("Native"),
+            ConfigStrategy::Portable => f.write_str("Portable"),
+            ConfigStrategy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
 }
 
 /// Changes the strategy to use which places the config file using XDG or the native OS's configuration.
 ///
 /// The default is the App Strategy see [`ConfigStrategy`] for more details on the strategy's affect.
 ///
+/// The strategy is shared by the whole process and used by the free functions
+/// like [`load`] and [`store`]. To avoid libraries or tests changing it under
+/// each other's feet, build a [`Confy`] handle with its own strategy instead.
+///
 /// ```rust,no_run
 /// # use confy::{ConfyError, ConfigStrategy, change_config_strategy};
 /// # use serde_derive::{Serialize, Deserialize};
@@ -243,10 +411,13 @@ pub enum ConfigStrategy {
 /// # Ok(())
 /// # }
 /// ```
+///
+/// [`load`]: fn.load.html
+/// [`store`]: fn.store.html
 pub fn change_config_strategy(changer: ConfigStrategy) {
     *STRATEGY
         .lock()
-        .expect("Error getting lock on Config Strategy") = changer;
+        .expect("Error getting lock on Config Strategy") = Arc::new(changer);
 }
 
 enum InternalStrategy {
@@ -254,37 +425,65 @@ enum InternalStrategy {
     NativeMac(app_strategy::Apple),
     NativeUnix(app_strategy::Unix),
     NativeWindows(app_strategy::Windows),
+    Portable(Portable),
+}
+
+impl InternalStrategy {
+    fn new(args: AppStrategyArgs, strategy: &ConfigStrategy) -> Result<Self, ConfyError> {
+        if let ConfigStrategy::Portable = strategy {
+            return Portable::new(&args.app_name).map(InternalStrategy::Portable);
+        }
+        if let Some(portable) = Portable::from_marker(&args.app_name) {
+            return Ok(InternalStrategy::Portable(portable));
+        }
+        let project = match strategy {
+            ConfigStrategy::App => choose_app_strategy(args).map(InternalStrategy::from),
+            ConfigStrategy::Native => choose_native_strategy(args).map(InternalStrategy::from),
+            ConfigStrategy::Portable => unreachable!("handled above"),
+            ConfigStrategy::Custom(_) => unreachable!("custom strategies are asked directly"),
+        };
+        project.map_err(|e| {
+            ConfyError::BadConfigDirectory(format!("could not determine home directory path: {e}"))
+        })
+    }
+}
+
+/// Forward a method call to whichever strategy is in use.
+macro_rules! delegate {
+    ($self:ident.$method:ident()) => {
+        match $self {
+            InternalStrategy::App(xdg) => xdg.$method(),
+            InternalStrategy::NativeMac(mac) => mac.$method(),
+            InternalStrategy::NativeUnix(unix) => unix.$method(),
+            InternalStrategy::NativeWindows(windows) => windows.$method(),
+            InternalStrategy::Portable(portable) => portable.$method(),
+        }
+    };
 }
 
-// we only every access the config dir function
 impl AppStrategy for InternalStrategy {
     fn home_dir(&self) -> &Path {
-        unimplemented!()
+        delegate!(self.home_dir())
     }
 
     fn config_dir(&self) -> PathBuf {
-        match self {
-            InternalStrategy::App(xdg) => xdg.config_dir(),
-            InternalStrategy::NativeMac(mac) => mac.config_dir(),
-            InternalStrategy::NativeUnix(unix) => unix.config_dir(),
-            InternalStrategy::NativeWindows(windows) => windows.config_dir(),
-        }
+        delegate!(self.config_dir())
     }
 
     fn data_dir(&self) -> PathBuf {
-        unimplemented!()
+        delegate!(self.data_dir())
     }
 
     fn cache_dir(&self) -> PathBuf {
-        unimplemented!()
+        delegate!(self.cache_dir())
     }
 
     fn state_dir(&self) -> Option<PathBuf> {
-        unimplemented!()
+        delegate!(self.state_dir())
     }
 
     fn runtime_dir(&self) -> Option<PathBuf> {
-        unimplemented!()
+        delegate!(self.runtime_dir())
     }
 }
 
@@ -341,7 +540,7 @@ pub fn load<'a, T: Serialize + DeserializeOwned + Default>(
     app_name: &str,
     config_name: impl Into<Option<&'a str>>,
 ) -> Result<T, ConfyError> {
-    get_configuration_file_path(app_name, config_name).and_then(load_path)
+    Confy::global(app_name).load(config_name)
 }
 
 /// Load an application configuration from a specified path.
@@ -353,97 +552,430 @@ pub fn load<'a, T: Serialize + DeserializeOwned + Default>(
 /// an arbitrary path instead of a system one.  For more information on errors
 /// and behavior, see [`load`]'s documentation.
 ///
+/// The format of the file is determined from its extension with
+/// [`Format::from_path`], so `load_path("config.yaml")` parses YAML as long
+/// as the `yaml_conf` feature is enabled.
+///
 /// [`load`]: fn.load.html
 pub fn load_path<T: Serialize + DeserializeOwned + Default>(
     path: impl AsRef<Path>,
 ) -> Result<T, ConfyError> {
-    match File::open(&path) {
-        Ok(mut cfg) => {
-            let cfg_string = cfg
-                .get_string()
-                .map_err(ConfyError::ReadConfigurationFileError)?;
+    let format = Format::from_path(&path)?;
+    load_path_format(path, format)
+}
 
-            #[cfg(any(feature = "toml_conf", feature = "basic_toml_conf"))]
-            {
-                let cfg_data = toml_from_str(&cfg_string);
-                cfg_data.map_err(ConfyError::BadTomlData)
-            }
-            #[cfg(feature = "yaml_conf")]
-            {
-                let cfg_data = serde_yaml::from_str(&cfg_string);
-                cfg_data.map_err(ConfyError::BadYamlData)
-            }
-            #[cfg(feature = "ron_conf")]
-            {
-                let cfg_data = ron::from_str(&cfg_string);
-                cfg_data.map_err(ConfyError::BadRonData)
-            }
+/// Load an application configuration from a specified path in a given format.
+///
+/// This is an alternate version of [`load_path`] that allows choosing the
+/// [`Format`] at runtime instead of looking at the file extension. For more
+/// information on errors and behavior, see [`load`]'s documentation.
+///
+/// ```rust,no_run
+/// # use confy::{ConfyError, Format};
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {}
+///
+/// let cfg: MyConfig = confy::load_path_format("my-config.toml", Format::Toml)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load_path`]: fn.load_path.html
+/// [`load`]: fn.load.html
+pub fn load_path_format<T: Serialize + DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+    format: Format,
+) -> Result<T, ConfyError> {
+    let path = path.as_ref();
+    {
+        let _lock = FileLock::shared(path)?;
+        if let Some(cfg) = read_locked(path, format)? {
+            return Ok(cfg);
         }
-        Err(ref e) if e.kind() == NotFound => {
-            if let Some(parent) = path.as_ref().parent() {
-                fs::create_dir_all(parent).map_err(ConfyError::DirectoryCreationFailed)?;
-            }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(ConfyError::DirectoryCreationFailed)?;
+    }
+    let _lock = FileLock::exclusive(path)?;
+    // another process may have created the file in the meantime
+    match read_locked(path, format)? {
+        Some(cfg) => Ok(cfg),
+        None => {
             let cfg = T::default();
-            store_path(path, &cfg)?;
+            write_locked(path, &cfg, None, format, 0)?;
             Ok(cfg)
         }
+    }
+}
+
+/// Load an application configuration without ever creating it
+///
+/// Unlike [`load`], nothing is written when the configuration file does not
+/// exist, neither the file nor its directory, and `None` is returned
+/// instead. This works on read-only file systems and keeps home directories
+/// free of files the user never asked for. To fall back to the default
+/// values in memory, see [`load_no_create`].
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {}
+///
+/// match confy::try_load::<MyConfig>("my-app-name", None)? {
+///     Some(cfg) => println!("configured"),
+///     None => println!("no configuration yet"),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load`]: fn.load.html
+/// [`load_no_create`]: fn.load_no_create.html
+pub fn try_load<'a, T: DeserializeOwned>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+) -> Result<Option<T>, ConfyError> {
+    Confy::global(app_name).try_load(config_name)
+}
+
+/// Load an application configuration from a specified path without ever creating it
+///
+/// Like [`load_path`], the format is determined from the file extension. For
+/// more information on errors and behavior, see [`try_load`]'s
+/// documentation.
+///
+/// [`load_path`]: fn.load_path.html
+/// [`try_load`]: fn.try_load.html
+pub fn try_load_path<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Option<T>, ConfyError> {
+    let format = Format::from_path(&path)?;
+    try_load_path_format(path.as_ref(), format)
+}
+
+/// Load an application configuration, using the default values without
+/// writing them if there is none
+///
+/// For more information on errors and behavior, see [`try_load`]'s
+/// documentation.
+///
+/// [`try_load`]: fn.try_load.html
+pub fn load_no_create<'a, T: DeserializeOwned + Default>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+) -> Result<T, ConfyError> {
+    try_load(app_name, config_name).map(Option::unwrap_or_default)
+}
+
+/// Load an application configuration from a specified path, using the
+/// default values without writing them if there is none
+///
+/// For more information on errors and behavior, see [`try_load`]'s
+/// documentation.
+///
+/// [`try_load`]: fn.try_load.html
+pub fn load_path_no_create<T: DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+) -> Result<T, ConfyError> {
+    try_load_path(path).map(Option::unwrap_or_default)
+}
+
+/// Read the configuration at `path` without creating the file, its directory
+/// or its lock file.
+fn try_load_path_format<T: DeserializeOwned>(
+    path: &Path,
+    format: Format,
+) -> Result<Option<T>, ConfyError> {
+    match try_read_path(path)? {
+        Some(cfg_bytes) => format.deserialize(&cfg_bytes).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Read the file at `path` like [`try_load_path_format`], without parsing it.
+fn try_read_path(path: &Path) -> Result<Option<Vec<u8>>, ConfyError> {
+    let _lock = FileLock::shared_existing(path)?;
+    read_bytes_locked(path)
+}
+
+/// Read the configuration at `path` while holding a lock on it, `None` if there is no file.
+fn read_locked<T: DeserializeOwned>(path: &Path, format: Format) -> Result<Option<T>, ConfyError> {
+    match read_bytes_locked(path)? {
+        Some(cfg_bytes) => format.deserialize(&cfg_bytes).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Read the file at `path` while holding a lock on it, `None` if there is no file.
+fn read_bytes_locked(path: &Path) -> Result<Option<Vec<u8>>, ConfyError> {
+    match File::open(path) {
+        Ok(mut cfg) => cfg
+            .get_bytes()
+            .map(Some)
+            .map_err(ConfyError::ReadConfigurationFileError),
+        Err(ref e) if e.kind() == NotFound => Ok(None),
         Err(e) => Err(ConfyError::GeneralLoadError(e)),
     }
 }
 
+/// Change an application configuration on disk
+///
+/// The configuration is loaded, handed to `op` for changing it and stored
+/// again, all while holding an exclusive lock on the file. This way no
+/// changes get lost when several processes update the same configuration at
+/// once, which can happen with separate calls to [`load`] and [`store`]. If
+/// there is no configuration file yet, `op` receives the default values.
+///
+/// Returns the changed configuration. Waiting for another process to release
+/// the lock fails with [`ConfyError::LockTimeout`] after ten seconds.
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     launches: u32,
+/// }
+///
+/// let cfg: MyConfig = confy::update("my-app-name", None, |cfg: &mut MyConfig| cfg.launches += 1)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load`]: fn.load.html
+/// [`store`]: fn.store.html
+pub fn update<'a, T, F>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+    op: F,
+) -> Result<T, ConfyError>
+where
+    T: Serialize + DeserializeOwned + Default,
+    F: FnOnce(&mut T),
+{
+    Confy::global(app_name).update(config_name, op)
+}
+
+/// Change an application configuration at a specified path
+///
+/// This is an alternate version of [`update`] that allows the specification of
+/// an arbitrary path instead of a system one. Like [`load_path`], the format is
+/// determined from the file extension. For more information on errors and
+/// behavior, see [`update`]'s documentation.
+///
+/// [`update`]: fn.update.html
+/// [`load_path`]: fn.load_path.html
+pub fn update_path<T, F>(path: impl AsRef<Path>, op: F) -> Result<T, ConfyError>
+where
+    T: Serialize + DeserializeOwned + Default,
+    F: FnOnce(&mut T),
+{
+    let path = path.as_ref();
+    update_path_format(path, op, Format::from_path(path)?, 0, None)
+}
+
+/// Update the configuration at `path`, upgrading it with `migrations` first
+/// and recording their version when storing it, if there are any.
+fn update_path_format<T, F>(
+    path: &Path,
+    op: F,
+    format: Format,
+    backups: usize,
+    migrations: Option<&Migrations>,
+) -> Result<T, ConfyError>
+where
+    T: Serialize + DeserializeOwned + Default,
+    F: FnOnce(&mut T),
+{
+    let config_dir = path
+        .parent()
+        .ok_or_else(|| ConfyError::BadConfigDirectory(format!("{path:?} is a root or prefix")))?;
+    fs::create_dir_all(config_dir).map_err(ConfyError::DirectoryCreationFailed)?;
+
+    let _lock = FileLock::exclusive(path)?;
+    let mut cfg = match read_bytes_locked(path)? {
+        Some(bytes) => migrate::deserialize(&bytes, format, migrations)?,
+        None => T::default(),
+    };
+    op(&mut cfg);
+    let bytes = migrate::serialize(&cfg, format, migrations)?;
+    write_bytes_locked(path, &bytes, None, backups)?;
+    Ok(cfg)
+}
+
+/// Load an application configuration from disk and override it from environment variables
+///
+/// The configuration is loaded like [`load`] does, afterwards every environment
+/// variable named `<prefix>_<FIELD>` replaces the value of that field. Fields of
+/// nested structs are separated by a double underscore, so with the prefix `MYAPP`
+/// the variable `MYAPP_SERVER__PORT` overrides `server.port`. Field names are
+/// matched ignoring case.
+///
+/// The values of the variables are parsed according to the type of the field
+/// they override, so `MYAPP_SERVER__PORT=8080` works for a `u16` field just like
+/// `MYAPP_VERBOSE=true` for a `bool`. Values that do not parse fail with
+/// [`ConfyError::EnvironmentOverrideError`]. Variables which name no field, like
+/// `MYAPP_CONFIG`, are ignored. Variables for the fields of an optional struct
+/// that is `None` have to give all of its required fields, or loading fails
+/// with [`ConfyError::UnsetEnvironmentOverride`]. The overrides only apply to the returned value,
+/// the configuration file is left as it is.
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct Server {
+///     port: u16,
+/// }
+///
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     server: Server,
+/// }
+///
+/// // MYAPP_SERVER__PORT=8080 my-app
+/// let cfg: MyConfig = confy::load_with_env("my-app-name", None, "MYAPP")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load`]: fn.load.html
+pub fn load_with_env<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+    prefix: &str,
+) -> Result<T, ConfyError> {
+    get_configuration_file_path(app_name, config_name)
+        .and_then(|path| load_path_with_env(path, prefix))
+}
+
+/// Load an application configuration from a specified path and override it from environment variables
+///
+/// This is an alternate version of [`load_with_env`] that allows the specification of
+/// an arbitrary path instead of a system one.  For more information on errors
+/// and behavior, see [`load_with_env`]'s documentation.
+///
+/// [`load_with_env`]: fn.load_with_env.html
+pub fn load_path_with_env<T: Serialize + DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+    prefix: &str,
+) -> Result<T, ConfyError> {
+    let cfg = load_path(path)?;
+    env::apply_overrides(cfg, prefix, env::vars())
+}
+
 /// Load an application configuration from a specified path.
 ///
 /// A new configuration file is created with `op`'s result if none
-/// exists or file content is incorrect.
+/// exists or file content is incorrect. The file is checked again and
+/// written under an exclusive lock, so a valid configuration another process
+/// stores in the meantime is kept.
+///
+/// Like [`load_path`], the format is determined from the file extension.
+/// To keep files that fail to parse instead of overwriting them, see
+/// [`load_or_else_quarantine`].
 ///
 /// This is an alternate version of [`load`] that allows the specification of
 /// an arbitrary path instead of a system one.  For more information on errors
 /// and behavior, see [`load`]'s documentation.
 ///
 /// [`load`]: fn.load.html
+/// [`load_or_else_quarantine`]: fn.load_or_else_quarantine.html
 pub fn load_or_else<T, F>(path: impl AsRef<Path>, op: F) -> Result<T, ConfyError>
 where
     T: DeserializeOwned + Serialize,
     F: FnOnce() -> T,
 {
-    let path_ref = path.as_ref();
-    let load_value = || {
-        let cfg = op();
-        if let Some(parent) = path.as_ref().parent() {
-            fs::create_dir_all(parent).map_err(ConfyError::DirectoryCreationFailed)?;
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    {
+        let _lock = FileLock::shared(path)?;
+        if let Some(cfg_bytes) = read_bytes_locked(path)?
+            && let Ok(cfg) = format.deserialize(&cfg_bytes)
+        {
+            return Ok(cfg);
         }
-        store_path(path_ref, &cfg)?;
-        Ok(cfg)
-    };
+    }
 
-    match File::open(path_ref) {
-        Ok(mut cfg) => {
-            let mut load_from_file = || {
-                let cfg_string = cfg
-                    .get_string()
-                    .map_err(ConfyError::ReadConfigurationFileError)?;
-
-                #[cfg(any(feature = "toml_conf", feature = "basic_toml_conf"))]
-                {
-                    let cfg_data = toml_from_str(&cfg_string);
-                    cfg_data.map_err(ConfyError::BadTomlData)
-                }
-                #[cfg(feature = "yaml_conf")]
-                {
-                    let cfg_data = serde_yaml::from_str(&cfg_string);
-                    cfg_data.map_err(ConfyError::BadYamlData)
-                }
-                #[cfg(feature = "ron_conf")]
-                {
-                    let cfg_data = ron::from_str(&cfg_string);
-                    cfg_data.map_err(ConfyError::BadRonData)
-                }
-            };
-            load_from_file().or_else(|_| load_value())
-        }
-        Err(ref e) if e.kind() == NotFound => load_value(),
-        Err(e) => Err(ConfyError::GeneralLoadError(e)),
+    create_config_dir(path)?;
+    let _lock = FileLock::exclusive(path)?;
+    // another process may have stored a valid configuration in the meantime
+    if let Some(cfg_bytes) = read_bytes_locked(path)?
+        && let Ok(cfg) = format.deserialize(&cfg_bytes)
+    {
+        return Ok(cfg);
+    }
+    let cfg = op();
+    write_locked(path, &cfg, None, format, 0)?;
+    Ok(cfg)
+}
+
+/// Load an application configuration from a specified path, moving the file aside if it is broken
+///
+/// This is an alternate version of [`load_or_else`] that does not throw away
+/// files which cannot be parsed. A typo in a hand edited file would otherwise
+/// replace all of the user's settings with `op`'s result. Instead, the broken
+/// file is renamed to `<name>.broken-<unix timestamp>` before the new
+/// configuration is written, and returned as [`Quarantined`] together with the
+/// error it failed to parse with, like [`ConfyError::BadTomlData`]. The
+/// application can then show the error as a warning, so the user can fix the
+/// file and move it back.
+///
+/// Errors reading the file, as opposed to parsing it, are returned as usual
+/// and leave the file in place.
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {}
+///
+/// let (cfg, quarantined) = confy::load_or_else_quarantine("my-config.toml", MyConfig::default)?;
+/// if let Some(quarantined) = quarantined {
+///     eprintln!(
+///         "warning: {} was moved to {}: {}",
+///         "my-config.toml",
+///         quarantined.path().display(),
+///         quarantined.error(),
+///     );
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load_or_else`]: fn.load_or_else.html
+pub fn load_or_else_quarantine<T, F>(
+    path: impl AsRef<Path>,
+    op: F,
+) -> Result<(T, Option<Quarantined>), ConfyError>
+where
+    T: DeserializeOwned + Serialize,
+    F: FnOnce() -> T,
+{
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(ConfyError::DirectoryCreationFailed)?;
     }
+
+    let _lock = FileLock::exclusive(path)?;
+    let target = resolve_symlink(path);
+    let quarantined = match read_bytes_locked(&target)? {
+        Some(cfg_bytes) => match format.deserialize(&cfg_bytes) {
+            Ok(cfg) => return Ok((cfg, None)),
+            Err(error) => Some(quarantine::quarantine(&target, error)?),
+        },
+        None => None,
+    };
+    let cfg = op();
+    write_locked(&target, &cfg, None, format, 0)?;
+    Ok((cfg, quarantined))
 }
 
 /// Save changes made to a configuration object
@@ -474,13 +1006,24 @@ where
 /// able to write the configuration file or if `confy`
 /// encounters an operating system or environment it does
 /// not support.
+///
+/// Concurrent calls from several processes are serialized by an advisory lock
+/// on a file next to the configuration, named like it with `.lock` appended.
+/// Loading takes a shared lock on it. To change a configuration without losing
+/// the changes of another process, use [`update`].
+///
+/// The file is never left half written, even if the process crashes: the
+/// configuration is written to a temporary file next to it, which is synced to
+/// disk and then renamed over the old file. If the configuration file is a
+/// symbolic link, the file it points to is replaced.
+///
+/// [`update`]: fn.update.html
 pub fn store<'a, T: Serialize>(
     app_name: &str,
     config_name: impl Into<Option<&'a str>>,
     cfg: T,
 ) -> Result<(), ConfyError> {
-    let path = get_configuration_file_path(app_name, config_name)?;
-    store_path(path, cfg)
+    Confy::global(app_name).store(config_name, cfg)
 }
 
 /// Save changes made to a configuration object at a specified path
@@ -496,8 +1039,7 @@ pub fn store_perms<'a, T: Serialize>(
     cfg: T,
     perms: Permissions,
 ) -> Result<(), ConfyError> {
-    let path = get_configuration_file_path(app_name, config_name)?;
-    store_path_perms(path, cfg, perms)
+    Confy::global(app_name).store_perms(config_name, cfg, perms)
 }
 
 /// Save changes made to a configuration object at a specified path
@@ -506,9 +1048,30 @@ pub fn store_perms<'a, T: Serialize>(
 /// an arbitrary path instead of a system one.  For more information on errors
 /// and behavior, see [`store`]'s documentation.
 ///
+/// The format is determined from the file extension with [`Format::from_path`],
+/// so `store_path("config.ron", cfg)` writes RON as long as the `ron_conf`
+/// feature is enabled.
+///
 /// [`store`]: fn.store.html
 pub fn store_path<T: Serialize>(path: impl AsRef<Path>, cfg: T) -> Result<(), ConfyError> {
-    do_store(path.as_ref(), cfg, None)
+    let path = path.as_ref();
+    do_store(path, cfg, None, Format::from_path(path)?, 0)
+}
+
+/// Save changes made to a configuration object at a specified path in a given format
+///
+/// This is an alternate version of [`store_path`] that allows choosing the
+/// [`Format`] at runtime instead of looking at the file extension. For more
+/// information on errors and behavior, see [`store`]'s documentation.
+///
+/// [`store_path`]: fn.store_path.html
+/// [`store`]: fn.store.html
+pub fn store_path_format<T: Serialize>(
+    path: impl AsRef<Path>,
+    cfg: T,
+    format: Format,
+) -> Result<(), ConfyError> {
+    do_store(path.as_ref(), cfg, None, format, 0)
 }
 
 /// Save changes made to a configuration object at a specified path
@@ -523,91 +1086,251 @@ pub fn store_path_perms<T: Serialize>(
     cfg: T,
     perms: Permissions,
 ) -> Result<(), ConfyError> {
-    do_store(path.as_ref(), cfg, Some(perms))
+    let path = path.as_ref();
+    do_store(path, cfg, Some(perms), Format::from_path(path)?, 0)
+}
+
+/// Save changes made to a configuration object at a specified path, keeping backups
+///
+/// This is an alternate version of [`store_path`] that keeps the previous
+/// contents of the file before overwriting it. Up to `backups` generations are
+/// kept, named like the file with the generation appended: `config.toml.1` is
+/// the most recent one, `config.toml.2` the one before and so on. The oldest
+/// one is dropped once there are more, as are those left from storing with a
+/// higher `backups` before. [`restore_backup`] rolls the file back
+/// to one of them. For more information on errors and behavior, see
+/// [`store`]'s documentation.
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {}
+///
+/// confy::store_path_backups("my-config.toml", MyConfig::default(), 3)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`store_path`]: fn.store_path.html
+/// [`restore_backup`]: fn.restore_backup.html
+/// [`store`]: fn.store.html
+pub fn store_path_backups<T: Serialize>(
+    path: impl AsRef<Path>,
+    cfg: T,
+    backups: usize,
+) -> Result<(), ConfyError> {
+    let path = path.as_ref();
+    do_store(path, cfg, None, Format::from_path(path)?, backups)
 }
 
 fn do_store<T: Serialize>(
     path: &Path,
     cfg: T,
     perms: Option<Permissions>,
+    format: Format,
+    backups: usize,
+) -> Result<(), ConfyError> {
+    store_bytes(path, &format.serialize(&cfg)?, perms, backups)
+}
+
+/// Store the already serialized configuration at `path` like [`do_store`].
+fn store_bytes(
+    path: &Path,
+    bytes: &[u8],
+    perms: Option<Permissions>,
+    backups: usize,
 ) -> Result<(), ConfyError> {
+    create_config_dir(path)?;
+    let _lock = FileLock::exclusive(path)?;
+    write_bytes_locked(path, bytes, perms, backups)
+}
+
+/// Create the directory the configuration file at `path` is placed in.
+fn create_config_dir(path: &Path) -> Result<(), ConfyError> {
     let config_dir = path
         .parent()
         .ok_or_else(|| ConfyError::BadConfigDirectory(format!("{path:?} is a root or prefix")))?;
-    fs::create_dir_all(config_dir).map_err(ConfyError::DirectoryCreationFailed)?;
+    fs::create_dir_all(config_dir).map_err(ConfyError::DirectoryCreationFailed)
+}
 
-    let s;
-    #[cfg(any(feature = "toml_conf", feature = "basic_toml_conf"))]
-    {
-        s = toml_to_string_pretty(&cfg).map_err(ConfyError::SerializeTomlError)?;
-    }
-    #[cfg(feature = "yaml_conf")]
-    {
-        s = serde_yaml::to_string(&cfg).map_err(ConfyError::SerializeYamlError)?;
-    }
-    #[cfg(feature = "ron_conf")]
-    {
-        let pretty_cfg = ron::ser::PrettyConfig::default();
-        s = ron::ser::to_string_pretty(&cfg, pretty_cfg).map_err(ConfyError::SerializeRonError)?;
-    }
+/// Write the configuration to `path` while holding an exclusive lock on it,
+/// keeping up to `backups` previous versions.
+fn write_locked<T: Serialize>(
+    path: &Path,
+    cfg: T,
+    perms: Option<Permissions>,
+    format: Format,
+    backups: usize,
+) -> Result<(), ConfyError> {
+    let s = format.serialize(&cfg)?;
+    write_bytes_locked(path, &s, perms, backups)
+}
 
-    let mut f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(path)
-        .map_err(ConfyError::OpenConfigurationFileError)?;
+/// Write the already serialized configuration to `path` like [`write_locked`].
+fn write_bytes_locked(
+    path: &Path,
+    bytes: &[u8],
+    perms: Option<Permissions>,
+    backups: usize,
+) -> Result<(), ConfyError> {
+    // write through symbolic links instead of replacing them
+    let target = resolve_symlink(path);
+    backup::rotate(&target, backups)?;
+    write_atomically(&target, bytes, perms)
+}
 
-    if let Some(p) = perms {
-        f.set_permissions(p)
-            .map_err(ConfyError::SetPermissionsFileError)?;
-    }
+/// Get the path of a file in the data directory of the application
+///
+/// The data directory is meant for files the application creates and wants to
+/// keep, like a history. Files are named like the configuration file, see
+/// [`get_configuration_file_path`], and placed according to the same
+/// [`ConfigStrategy`].
+///
+/// [`get_configuration_file_path`]: fn.get_configuration_file_path.html
+pub fn data_path<'a>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+) -> Result<PathBuf, ConfyError> {
+    Confy::global(app_name).data_path(name)
+}
+
+/// Get the path of a file in the cache directory of the application
+///
+/// The cache directory is meant for files which can be recreated when they are
+/// lost. Files are named and placed like for [`data_path`].
+///
+/// [`data_path`]: fn.data_path.html
+pub fn cache_path<'a>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+) -> Result<PathBuf, ConfyError> {
+    Confy::global(app_name).cache_path(name)
+}
+
+/// Get the path of a file in the state directory of the application
+///
+/// The state directory is meant for files which should survive a restart but
+/// are not worth backing up. Only the XDG and Unix layouts have a state
+/// directory, elsewhere the data directory is used instead. Files are named and
+/// placed like for [`data_path`].
+///
+/// [`data_path`]: fn.data_path.html
+pub fn state_path<'a>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+) -> Result<PathBuf, ConfyError> {
+    Confy::global(app_name).state_path(name)
+}
+
+/// Load a file from the data directory of the application
+///
+/// This is an alternate version of [`load`] for the file returned by
+/// [`data_path`]. For more information on errors and behavior, see [`load`]'s
+/// documentation.
+///
+/// [`load`]: fn.load.html
+/// [`data_path`]: fn.data_path.html
+pub fn load_data<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+) -> Result<T, ConfyError> {
+    Confy::global(app_name).load_data(name)
+}
+
+/// Save a file in the data directory of the application
+///
+/// This is an alternate version of [`store`] for the file returned by
+/// [`data_path`]. For more information on errors and behavior, see [`store`]'s
+/// documentation.
+///
+/// [`store`]: fn.store.html
+/// [`data_path`]: fn.data_path.html
+pub fn store_data<'a, T: Serialize>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+    cfg: T,
+) -> Result<(), ConfyError> {
+    Confy::global(app_name).store_data(name, cfg)
+}
+
+/// Load a file from the cache directory of the application
+///
+/// This is an alternate version of [`load`] for the file returned by
+/// [`cache_path`]. For more information on errors and behavior, see [`load`]'s
+/// documentation.
+///
+/// [`load`]: fn.load.html
+/// [`cache_path`]: fn.cache_path.html
+pub fn load_cache<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+) -> Result<T, ConfyError> {
+    Confy::global(app_name).load_cache(name)
+}
+
+/// Save a file in the cache directory of the application
+///
+/// This is an alternate version of [`store`] for the file returned by
+/// [`cache_path`]. For more information on errors and behavior, see [`store`]'s
+/// documentation.
+///
+/// [`store`]: fn.store.html
+/// [`cache_path`]: fn.cache_path.html
+pub fn store_cache<'a, T: Serialize>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+    cfg: T,
+) -> Result<(), ConfyError> {
+    Confy::global(app_name).store_cache(name, cfg)
+}
+
+/// Load a file from the state directory of the application
+///
+/// This is an alternate version of [`load`] for the file returned by
+/// [`state_path`]. For more information on errors and behavior, see [`load`]'s
+/// documentation.
+///
+/// [`load`]: fn.load.html
+/// [`state_path`]: fn.state_path.html
+pub fn load_state<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+) -> Result<T, ConfyError> {
+    Confy::global(app_name).load_state(name)
+}
 
-    f.write_all(s.as_bytes())
-        .map_err(ConfyError::WriteConfigurationFileError)?;
-    Ok(())
+/// Save a file in the state directory of the application
+///
+/// This is an alternate version of [`store`] for the file returned by
+/// [`state_path`]. For more information on errors and behavior, see [`store`]'s
+/// documentation.
+///
+/// [`store`]: fn.store.html
+/// [`state_path`]: fn.state_path.html
+pub fn store_state<'a, T: Serialize>(
+    app_name: &str,
+    name: impl Into<Option<&'a str>>,
+    cfg: T,
+) -> Result<(), ConfyError> {
+    Confy::global(app_name).store_state(name, cfg)
 }
 
 /// Get the configuration file path used by [`load`] and [`store`]
 ///
 /// This is useful if you want to show where the configuration file is to your user.
 ///
+/// To let environment variables relocate the file, build a [`Confy`] handle
+/// with [`ConfyBuilder::location_env`] and use [`Confy::config_location`].
+///
 /// [`load`]: fn.load.html
 /// [`store`]: fn.store.html
 pub fn get_configuration_file_path<'a>(
     app_name: &str,
     config_name: impl Into<Option<&'a str>>,
 ) -> Result<PathBuf, ConfyError> {
-    let config_name = config_name.into().unwrap_or("default-config");
-    let project: InternalStrategy = match *STRATEGY
-        .lock()
-        .expect("Error getting lock on config strategy")
-    {
-        ConfigStrategy::App => choose_app_strategy(AppStrategyArgs {
-            top_level_domain: "rs".to_string(),
-            author: "".to_string(),
-            app_name: app_name.to_string(),
-        })
-        .map_err(|e| {
-            ConfyError::BadConfigDirectory(format!("could not determine home directory path: {e}"))
-        })?
-        .into(),
-        ConfigStrategy::Native => choose_native_strategy(AppStrategyArgs {
-            top_level_domain: "rs".to_string(),
-            author: "".to_string(),
-            app_name: app_name.to_string(),
-        })
-        .map_err(|e| {
-            ConfyError::BadConfigDirectory(format!("could not determine home directory path: {e}"))
-        })?
-        .into(),
-    };
-
-    let mut path = project.config_dir();
-
-    path.push(format!("{config_name}.{EXTENSION}"));
-
-    Ok(path)
+    Confy::global(app_name).get_configuration_file_path(config_name)
 }
 
 #[cfg(test)]
@@ -615,6 +1338,9 @@ mod tests {
     use super::*;
     use serde::Serializer;
     use serde_derive::{Deserialize, Serialize};
+    use std::ffi::OsString;
+    use std::fs::OpenOptions;
+    use std::io::Write;
 
     #[cfg(unix)]
     use std::os::unix::fs::PermissionsExt;
@@ -625,15 +1351,28 @@ mod tests {
         count: usize,
     }
 
+    /// Every format, whether its feature is enabled or not.
+    const FORMATS: &[Format] = &[
+        Format::Toml,
+        Format::Yaml,
+        Format::Ron,
+        Format::Json,
+        Format::Json5,
+        Format::Ini,
+        Format::Kdl,
+        Format::Cbor,
+        Format::MessagePack,
+    ];
+
     /// Run a test function with a temporary config path as fixture.
-    fn with_config_path(test_fn: fn(&Path)) {
+    fn with_config_path(test_fn: impl FnOnce(&Path)) {
         let config_dir = tempfile::tempdir().expect("creating test fixture failed");
         // config_path should roughly correspond to the result of `get_configuration_file_path("example-app", "example-config")`
         let config_path = config_dir
             .path()
             .join("example-app")
             .join("example-config")
-            .with_extension(EXTENSION);
+            .with_extension(Format::default().extension());
         test_fn(&config_path);
         config_dir.close().expect("removing test fixture failed");
     }
@@ -663,7 +1402,7 @@ mod tests {
         with_config_path(|path| {
             fs::create_dir_all(path.parent().unwrap()).unwrap();
             let mut file = File::create(path).expect("creating file failed");
-            file.write("some normal text".as_bytes())
+            file.write_all("some normal text".as_bytes())
                 .expect("write to file failed");
             drop(file);
 
@@ -693,8 +1432,12 @@ mod tests {
 
     #[test]
     fn test_store_path_native() {
-        // change the strategy first then the app will always use it
-        change_config_strategy(ConfigStrategy::Native);
+        let extension = Format::default().extension();
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Native)
+            .build()
+            .expect("building confy failed");
 
         with_config_path(|path| {
             let config: ExampleConfig = ExampleConfig {
@@ -702,13 +1445,13 @@ mod tests {
                 count: 42,
             };
 
-            let file_path = get_configuration_file_path("example-app", "example-config").unwrap();
+            let file_path = confy.get_configuration_file_path("example-config").unwrap();
 
             if cfg!(target_os = "macos") {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}/Library/Preferences/rs.example-app/example-config.toml",
+                        "{}/Library/Preferences/rs.example-app/example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     )),
                 );
@@ -716,7 +1459,7 @@ mod tests {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}/.config/example-app/example-config.toml",
+                        "{}/.config/example-app/example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     ))
                 );
@@ -725,7 +1468,7 @@ mod tests {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}\\AppData\\Roaming\\example-app\\config\\example-config.toml",
+                        "{}\\AppData\\Roaming\\example-app\\config\\example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     )),
                 );
@@ -738,10 +1481,19 @@ mod tests {
         })
     }
 
+    /// Handles with different strategies live side by side without touching the global one.
     #[test]
     fn test_store_path_change() {
-        // change the strategy first to native
-        change_config_strategy(ConfigStrategy::Native);
+        let extension = Format::default().extension();
+        let native = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Native)
+            .build()
+            .expect("building confy failed");
+        let app = Confy::builder()
+            .app_name("example-app")
+            .build()
+            .expect("building confy failed");
 
         with_config_path(|path| {
             let config: ExampleConfig = ExampleConfig {
@@ -749,13 +1501,15 @@ mod tests {
                 count: 42,
             };
 
-            let file_path = get_configuration_file_path("example-app", "example-config").unwrap();
+            let file_path = native
+                .get_configuration_file_path("example-config")
+                .unwrap();
 
             if cfg!(target_os = "macos") {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}/Library/Preferences/rs.example-app/example-config.toml",
+                        "{}/Library/Preferences/rs.example-app/example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     )),
                 );
@@ -763,7 +1517,7 @@ mod tests {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}/.config/example-app/example-config.toml",
+                        "{}/.config/example-app/example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     ))
                 );
@@ -772,30 +1526,19 @@ mod tests {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}\\AppData\\Roaming\\example-app\\config\\example-config.toml",
+                        "{}\\AppData\\Roaming\\example-app\\config\\example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     )),
                 );
             }
 
-            //change the strategy back to Application style
-            change_config_strategy(ConfigStrategy::App);
+            let file_path = app.get_configuration_file_path("example-config").unwrap();
 
-            let file_path = get_configuration_file_path("example-app", "example-config").unwrap();
-
-            if cfg!(target_os = "macos") {
-                assert_eq!(
-                    file_path,
-                    Path::new(&format!(
-                        "{}/.config/example-app/example-config.toml",
-                        std::env::home_dir().unwrap().display()
-                    )),
-                );
-            } else if cfg!(target_os = "linux") {
+            if cfg!(any(target_os = "macos", target_os = "linux")) {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}/.config/example-app/example-config.toml",
+                        "{}/.config/example-app/example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     ))
                 );
@@ -804,7 +1547,7 @@ mod tests {
                 assert_eq!(
                     file_path,
                     Path::new(&format!(
-                        "{}\\AppData\\Roaming\\example-app\\config\\example-config.toml",
+                        "{}\\AppData\\Roaming\\example-app\\config\\example-config.{extension}",
                         std::env::home_dir().unwrap().display()
                     )),
                 );
@@ -817,108 +1560,911 @@ mod tests {
         })
     }
 
-    /// [`store_path_perms`] stores [`ExampleConfig`], with only read permission for owner (UNIX).
+    /// The qualifier and organization end up in the paths of the native strategy.
     #[test]
-    #[cfg(unix)]
-    fn test_store_path_perms() {
-        with_config_path(|path| {
-            let config: ExampleConfig = ExampleConfig {
-                name: "Secret".to_string(),
-                count: 16549,
-            };
-            store_path_perms(path, &config, Permissions::from_mode(0o600))
-                .expect("store_path_perms failed");
-            let loaded = load_path(path).expect("load_path failed");
-            assert_eq!(config, loaded);
-        })
+    fn test_builder_qualifier_organization() {
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .qualifier("com")
+            .organization("Acme Corp")
+            .strategy(ConfigStrategy::Native)
+            .build()
+            .expect("building confy failed");
+        assert_eq!(confy.qualifier(), "com");
+        assert_eq!(confy.organization(), "Acme Corp");
+
+        let file_path = confy.get_configuration_file_path("example-config").unwrap();
+        let home = std::env::home_dir().unwrap();
+        let file_name = format!("example-config.{}", Format::default().extension());
+        if cfg!(target_os = "macos") {
+            assert_eq!(
+                file_path,
+                home.join("Library/Preferences/com.acme-corp.example-app")
+                    .join(file_name)
+            );
+        } else if cfg!(target_os = "linux") {
+            assert_eq!(file_path, home.join(".config/example-app").join(file_name));
+        } else {
+            //windows
+            assert_eq!(
+                file_path,
+                home.join("AppData\\Roaming\\Acme Corp\\example-app\\config")
+                    .join(file_name)
+            );
+        }
     }
 
-    /// [`store_path_perms`] stores [`ExampleConfig`], as read-only.
+    /// Data, cache and state files are placed in the directories of the strategy.
     #[test]
-    fn test_store_path_perms_readonly() {
-        with_config_path(|path| {
-            let config: ExampleConfig = ExampleConfig {
-                name: "Soon read-only".to_string(),
-                count: 27115,
-            };
-            store_path(path, &config).expect("store_path failed");
+    fn test_data_cache_state_paths() {
+        let args = || AppStrategyArgs {
+            top_level_domain: "rs".to_string(),
+            author: "".to_string(),
+            app_name: "example-app".to_string(),
+        };
+        let file_name = format!("example.{}", Format::default().extension());
+
+        for strategy in [ConfigStrategy::App, ConfigStrategy::Native] {
+            let expected = InternalStrategy::new(args(), &strategy).unwrap();
+            let confy = Confy::builder()
+                .app_name("example-app")
+                .strategy(strategy)
+                .build()
+                .expect("building confy failed");
+
+            assert_eq!(
+                confy.data_path("example").unwrap(),
+                expected.data_dir().join(&file_name)
+            );
+            assert_eq!(
+                confy.cache_path("example").unwrap(),
+                expected.cache_dir().join(&file_name)
+            );
+            assert_eq!(
+                confy.state_path("example").unwrap(),
+                expected
+                    .state_dir()
+                    .unwrap_or_else(|| expected.data_dir())
+                    .join(&file_name)
+            );
+            assert_ne!(
+                confy.data_path("example").unwrap(),
+                confy.get_configuration_file_path("example").unwrap()
+            );
+        }
+    }
 
-            let metadata = fs::metadata(path).expect("reading metadata failed");
-            let mut permissions = metadata.permissions();
-            permissions.set_readonly(true);
+    /// `<PREFIX>_CONFIG` and `<PREFIX>_CONFIG_DIR` only relocate the file when opted in.
+    #[test]
+    fn test_config_location_env() {
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+        let file_name = format!("other.{}", Format::default().extension());
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .location_env("EXAMPLE")
+            .build()
+            .expect("building confy failed");
+        let strategy_path = Confy::builder()
+            .app_name("example-app")
+            .build()
+            .and_then(|confy| confy.get_configuration_file_path(None))
+            .unwrap();
+
+        let location = confy
+            .config_location_with(None, vars(&[("EXAMPLE_CONFIG", "/etc/example.yml")]))
+            .unwrap();
+        assert_eq!(location.path(), Path::new("/etc/example.yml"));
+        assert_eq!(
+            location.source(),
+            &LocationSource::EnvFile("EXAMPLE_CONFIG".to_string())
+        );
+        assert!(location.is_override());
+
+        // the file only replaces the default configuration
+        let location = confy
+            .config_location_with(
+                Some("other"),
+                vars(&[("EXAMPLE_CONFIG", "/etc/example.yml")]),
+            )
+            .unwrap();
+        assert_eq!(location.source(), &LocationSource::Strategy);
+
+        let both = vars(&[
+            ("EXAMPLE_CONFIG", "/etc/example.yml"),
+            ("EXAMPLE_CONFIG_DIR", "/srv/example"),
+        ]);
+        let location = confy.config_location_with(Some("other"), both).unwrap();
+        assert_eq!(location.path(), Path::new("/srv/example").join(&file_name));
+        assert_eq!(
+            location.source(),
+            &LocationSource::EnvDir("EXAMPLE_CONFIG_DIR".to_string())
+        );
+        let location = confy.config_location_with(None, both).unwrap();
+        assert_eq!(location.path(), Path::new("/etc/example.yml"));
+
+        let location = confy
+            .config_location_with(None, vars(&[("EXAMPLE_CONFIG", "")]))
+            .unwrap();
+        assert_eq!(location.path(), strategy_path);
+        assert!(!location.is_override());
+
+        // without opting in, the variables are ignored
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .build()
+            .expect("building confy failed");
+        let location = confy
+            .config_location_with(None, vars(&[("EXAMPLE_CONFIG", "/etc/example.yml")]))
+            .unwrap();
+        assert_eq!(location.path(), strategy_path);
+        assert_eq!(location.source(), &LocationSource::Strategy);
+    }
 
-            store_path_perms(path, &config, permissions).expect("store_path_perms failed");
+    /// The portable strategy places all files next to the executable.
+    #[test]
+    fn test_portable_strategy() {
+        let exe_dir = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let file_name = format!("example-config.{}", Format::default().extension());
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Portable)
+            .build()
+            .expect("building confy failed");
 
-            assert!(
-                fs::metadata(path)
-                    .expect("reading metadata failed")
-                    .permissions()
-                    .readonly()
-            );
-        })
+        assert_eq!(
+            confy.get_configuration_file_path("example-config").unwrap(),
+            exe_dir.join("config").join("example-app").join(&file_name)
+        );
+        assert_eq!(
+            confy.data_path("example-config").unwrap(),
+            exe_dir.join("data").join("example-app").join(&file_name)
+        );
+        assert_eq!(
+            confy.cache_path("example-config").unwrap(),
+            exe_dir.join("cache").join("example-app").join(&file_name)
+        );
+        assert_eq!(
+            confy.state_path("example-config").unwrap(),
+            exe_dir.join("state").join("example-app").join(&file_name)
+        );
     }
 
-    /// [`store_path`] fails when given a root path.
+    /// A marker file named after the application turns on the portable strategy.
     #[test]
-    fn test_store_path_root_error() {
-        let err = store_path(PathBuf::from("/"), &ExampleConfig::default())
-            .expect_err("store_path should fail");
+    fn test_portable_marker() {
+        let dir = tempfile::tempdir().expect("creating test fixture failed");
+        assert!(Portable::from_marker_in(dir.path(), "example-app").is_none());
+
+        File::create(dir.path().join("other-app.portable")).unwrap();
+        assert!(Portable::from_marker_in(dir.path(), "example-app").is_none());
+
+        File::create(dir.path().join(portable::marker_name("example-app"))).unwrap();
+        let portable =
+            Portable::from_marker_in(dir.path(), "example-app").expect("marker not detected");
         assert_eq!(
-            err.to_string(),
-            r#"Bad configuration directory: "/" is a root or prefix"#,
-        )
+            portable.config_dir(),
+            dir.path().join("config").join("example-app")
+        );
     }
 
-    struct CannotSerialize;
+    /// Portable applications sharing a directory keep their files apart.
+    #[test]
+    fn test_portable_shared_dir() {
+        let dir = tempfile::tempdir().expect("creating test fixture failed");
+        File::create(dir.path().join(portable::marker_name("example-app"))).unwrap();
+        File::create(dir.path().join(portable::marker_name("other-app"))).unwrap();
+        let example =
+            Portable::from_marker_in(dir.path(), "example-app").expect("marker not detected");
+        let other = Portable::from_marker_in(dir.path(), "other-app").expect("marker not detected");
+
+        assert_ne!(example.config_dir(), other.config_dir());
+        assert_ne!(example.data_dir(), other.data_dir());
+        assert_ne!(example.cache_dir(), other.cache_dir());
+        assert_ne!(example.state_dir(), other.state_dir());
+        assert_eq!(other.data_dir(), dir.path().join("data").join("other-app"));
+    }
 
-    impl Serialize for CannotSerialize {
-        fn serialize<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
-        where
-            S: Serializer,
-        {
-            use serde::ser::Error;
-            Err(S::Error::custom("cannot serialize CannotSerialize"))
+    /// Places the files of every application below a fixed root.
+    struct RootLocator(PathBuf);
+
+    impl ConfigLocator for RootLocator {
+        fn config_dir(&self, app_name: &str) -> Result<PathBuf, ConfyError> {
+            if app_name == "unmounted-app" {
+                return Err(ConfyError::BadConfigDirectory("not mounted".to_string()));
+            }
+            Ok(self.0.join(app_name))
         }
     }
 
-    /// Verify that if you call store_path() with an object that fails to serialize,
-    /// the file on disk will not be overwritten or truncated.
-    #[test]
-    fn test_store_path_atomic() -> Result<(), ConfyError> {
-        let tmp = tempfile::NamedTempFile::new().expect("Failed to create NamedTempFile");
-        let path = tmp.path();
-        let message = "Hello world!";
+    /// Two migrations, renaming `title` to `name` and then `amount` to `count`,
+    /// counting how often they run.
+    fn example_migrations(runs: &Arc<Mutex<Vec<u64>>>) -> Migrations {
+        let rename = |runs: &Arc<Mutex<Vec<u64>>>, version, from: &'static str, to| {
+            let runs = Arc::clone(runs);
+            move |cfg: &mut serde_json::Value| {
+                runs.lock().unwrap().push(version);
+                let value = cfg.as_object_mut().unwrap().remove(from).unwrap();
+                cfg[to] = value;
+            }
+        };
+        Migrations::new()
+            .migration(rename(runs, 2, "title", "name"))
+            .migration(rename(runs, 3, "amount", "count"))
+    }
 
-        // Write to file.
-        {
-            let mut f = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(path)
-                .map_err(ConfyError::OpenConfigurationFileError)?;
+    /// The version recorded in the file at `path`, read like the configuration
+    /// is, as formats like INI read numbers back as strings.
+    fn recorded_version(path: &Path) -> Option<u64> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: Option<u64>,
+        }
 
-            f.write_all(message.as_bytes())
-                .map_err(ConfyError::WriteConfigurationFileError)?;
+        let versioned: Versioned = Format::default()
+            .deserialize(&fs::read(path).unwrap())
+            .unwrap();
+        versioned.version
+    }
 
-            f.flush().map_err(ConfyError::WriteConfigurationFileError)?;
+    /// [`load_path_migrated`] upgrades old files step by step and stores them again.
+    #[test]
+    fn test_load_path_migrated() {
+        // RON stores the version through the struct, see test_load_path_migrated_ron
+        if Format::default().has_typed_structs() {
+            return;
         }
+        with_config_path(|path| {
+            let runs = Arc::new(Mutex::new(Vec::new()));
+            let migrations = example_migrations(&runs);
+            assert_eq!(migrations.version(), 3);
+            let write = |value: serde_json::Value| {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, Format::default().serialize(&value).unwrap()).unwrap();
+            };
+            let version = || recorded_version(path);
+            let expected = ExampleConfig {
+                name: "Test".to_string(),
+                count: 42,
+            };
 
-        // Call store_path() to overwrite file with an object that fails to serialize.
-        let store_result = store_path(path, CannotSerialize);
-        assert!(matches!(store_result, Err(_)));
+            // without a version, the file is from before the first migration
+            write(serde_json::json!({ "title": "Test", "amount": 42 }));
+            let loaded: ExampleConfig =
+                load_path_migrated(path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded, expected);
+            assert_eq!(*runs.lock().unwrap(), [2, 3]);
+            assert_eq!(version(), Some(3));
+
+            // up to date files are left alone
+            let loaded: ExampleConfig =
+                load_path_migrated(path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded, expected);
+            assert_eq!(*runs.lock().unwrap(), [2, 3]);
+
+            write(serde_json::json!({ "version": 2, "name": "Test", "amount": 42 }));
+            let loaded: ExampleConfig =
+                load_path_migrated(path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded, expected);
+            assert_eq!(*runs.lock().unwrap(), [2, 3, 3]);
+
+            write(serde_json::json!({ "version": 4, "name": "Test", "count": 42 }));
+            assert!(matches!(
+                load_path_migrated::<ExampleConfig>(path, &migrations),
+                Err(ConfyError::VersionTooNew(4, 3))
+            ));
+
+            // new files start out at the current version
+            fs::remove_file(path).unwrap();
+            let loaded: ExampleConfig =
+                load_path_migrated(path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded, ExampleConfig::default());
+            assert_eq!(version(), Some(3));
+            assert_eq!(*runs.lock().unwrap(), [2, 3, 3]);
+        })
+    }
 
-        // Ensure file was not overwritten.
-        let buf = {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .open(path)
-                .map_err(ConfyError::OpenConfigurationFileError)?;
+    /// Migrated files with nested structs keep plain values before tables, as TOML requires.
+    #[test]
+    fn test_load_path_migrated_nested() {
+        // RON stores the version through the struct, see test_load_path_migrated_ron
+        if Format::default().has_typed_structs() {
+            return;
+        }
+        #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+        struct NestedConfig {
+            name: String,
+            count: usize,
+            server: ServerSection,
+        }
 
-            let mut buf = String::new();
+        with_config_path(|path| {
+            let runs = Arc::new(Mutex::new(Vec::new()));
+            let migrations = example_migrations(&runs);
+            let file = serde_json::json!({
+                "title": "Test",
+                "server": { "host": "example.org", "port": 80, "ratio": 0.5 },
+                "amount": 42,
+            });
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let bytes = Format::default()
+                .serialize(&value::Tree::from(file))
+                .unwrap();
+            fs::write(path, bytes).unwrap();
+
+            let loaded: NestedConfig =
+                load_path_migrated(path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded.name, "Test");
+            assert_eq!(loaded.count, 42);
+            assert_eq!(loaded.server.port, 80);
+            let loaded: NestedConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded.server.host, "example.org");
+        })
+    }
 
-            use std::io::Read;
-            f.read_to_string(&mut buf)
+    /// [`load_path_migrated`] writes RON files as structs, with their version field.
+    #[test]
+    #[cfg(feature = "ron_conf")]
+    fn test_load_path_migrated_ron() {
+        #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+        struct VersionedConfig {
+            version: u64,
+            name: String,
+            count: usize,
+        }
+
+        with_config_path(|path| {
+            let path = path.with_extension("ron");
+            let runs = Arc::new(Mutex::new(Vec::new()));
+            let migrations = example_migrations(&runs);
+            let expected = VersionedConfig {
+                version: 3,
+                name: "Test".to_string(),
+                count: 42,
+            };
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, r#"(title: "Test", amount: 42)"#).unwrap();
+            let loaded: VersionedConfig =
+                load_path_migrated(&path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded, expected);
+            let loaded: VersionedConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(loaded, expected);
+
+            fs::remove_file(&path).unwrap();
+            let loaded: VersionedConfig =
+                load_path_migrated(&path, &migrations).expect("load_path_migrated failed");
+            assert_eq!(loaded.version, 3);
+            assert_eq!(*runs.lock().unwrap(), [2, 3]);
+
+            // without the field, the version cannot be recorded
+            fs::write(&path, r#"(title: "Test", amount: 42)"#).unwrap();
+            let err = load_path_migrated::<ExampleConfig>(&path, &migrations)
+                .expect_err("load_path_migrated should fail");
+            assert!(matches!(err, ConfyError::VersionFieldMissing(field) if field == "version"));
+        })
+    }
+
+    /// Handles with migrations record the current version in RON files on store.
+    #[test]
+    #[cfg(feature = "ron_conf")]
+    fn test_builder_migrations_ron() {
+        #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+        struct VersionedConfig {
+            version: u64,
+            name: String,
+        }
+
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .format(Format::Ron)
+            .migrations(example_migrations(&runs))
+            .build()
+            .expect("building confy failed");
+
+        let config = VersionedConfig {
+            version: 0,
+            name: "Test".to_string(),
+        };
+        confy.store(None, &config).expect("store failed");
+        let path = confy.get_configuration_file_path(None).unwrap();
+        let stored: VersionedConfig = load_path(&path).expect("load_path failed");
+        assert_eq!(stored.version, 3);
+        assert_eq!(stored.name, "Test");
+
+        let err = confy
+            .store(None, ExampleConfig::default())
+            .expect_err("store should fail");
+        assert!(matches!(err, ConfyError::VersionFieldMissing(_)));
+    }
+
+    /// [`ConfyBuilder::migrations`] migrates on load and records the version on store.
+    #[test]
+    fn test_builder_migrations() {
+        // RON stores the version through the struct, see test_load_path_migrated_ron
+        if Format::default().has_typed_structs() {
+            return;
+        }
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .migrations(example_migrations(&runs))
+            .build()
+            .expect("building confy failed");
+        assert_eq!(confy.migrations().map(Migrations::version), Some(3));
+
+        let config = ExampleConfig {
+            name: "Test".to_string(),
+            count: 42,
+        };
+        confy.store(None, &config).expect("store failed");
+        let path = confy.get_configuration_file_path(None).unwrap();
+        assert_eq!(recorded_version(&path), Some(3));
+
+        let loaded: ExampleConfig = confy.load(None).expect("load failed");
+        assert_eq!(loaded, config);
+        assert!(runs.lock().unwrap().is_empty());
+
+        // every other read migrates and every other write records the version
+        let version = || recorded_version(&path);
+        let write_outdated = || {
+            let file = serde_json::json!({ "title": "Test", "amount": 42 });
+            fs::write(&path, Format::default().serialize(&file).unwrap()).unwrap();
+        };
+        confy
+            .update(None, |cfg: &mut ExampleConfig| cfg.count = 5)
+            .expect("update failed");
+        assert_eq!(version(), Some(3));
+        let loaded: ExampleConfig = confy.load(None).expect("load failed");
+        assert_eq!(loaded.count, 5);
+        assert!(runs.lock().unwrap().is_empty());
+
+        write_outdated();
+        let loaded: Option<ExampleConfig> = confy.try_load(None).expect("try_load failed");
+        assert_eq!(loaded.as_ref(), Some(&config));
+        assert_eq!(*runs.lock().unwrap(), [2, 3]);
+        assert_eq!(version(), None);
+        let updated: ExampleConfig = confy
+            .update(None, |cfg: &mut ExampleConfig| cfg.count += 1)
+            .expect("update failed");
+        assert_eq!(updated.count, 43);
+        assert_eq!(version(), Some(3));
+
+        write_outdated();
+        let merged: Merged<ExampleConfig> = confy.load_merged(None).expect("load_merged failed");
+        assert_eq!(*merged, config);
+        assert!(merged.unknown_keys().is_empty());
+        assert_eq!(version(), Some(3));
+        assert_eq!(*runs.lock().unwrap(), [2, 3, 2, 3, 2, 3]);
+    }
+
+    /// Without [`ConfyBuilder::create_missing`], outdated files are only migrated in memory.
+    #[test]
+    fn test_builder_migrations_no_create() {
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .migrations(example_migrations(&runs))
+            .create_missing(false)
+            .build()
+            .expect("building confy failed");
+        let path = confy.get_configuration_file_path(None).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = serde_json::json!({ "title": "Test", "amount": 42 });
+        let bytes = Format::default().serialize(&file).unwrap();
+        fs::write(&path, &bytes).unwrap();
+        let expected = ExampleConfig {
+            name: "Test".to_string(),
+            count: 42,
+        };
+
+        let loaded: ExampleConfig = confy.load(None).expect("load failed");
+        assert_eq!(loaded, expected);
+        let merged: Merged<ExampleConfig> = confy.load_merged(None).expect("load_merged failed");
+        assert_eq!(*merged, expected);
+        assert!(merged.unknown_keys().is_empty());
+        assert_eq!(*runs.lock().unwrap(), [2, 3, 2, 3]);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    /// [`try_load_path`] and [`load_path_no_create`] leave missing files alone.
+    #[test]
+    fn test_try_load_path() {
+        with_config_path(|path| {
+            let loaded: Option<ExampleConfig> = try_load_path(path).expect("try_load_path failed");
+            assert!(loaded.is_none());
+            let loaded: ExampleConfig =
+                load_path_no_create(path).expect("load_path_no_create failed");
+            assert_eq!(loaded, ExampleConfig::default());
+            assert!(!path.parent().unwrap().exists());
+
+            let config = ExampleConfig {
+                name: "Test".to_string(),
+                count: 42,
+            };
+            store_path(path, &config).expect("store_path failed");
+            let loaded: Option<ExampleConfig> = try_load_path(path).expect("try_load_path failed");
+            assert_eq!(loaded, Some(config));
+        })
+    }
+
+    /// [`ConfyBuilder::create_missing`] turns off creating files when loading.
+    #[test]
+    fn test_builder_create_missing() {
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .create_missing(false)
+            .build()
+            .expect("building confy failed");
+        assert!(!confy.creates_missing());
+
+        let config: ExampleConfig = confy.load(None).expect("load failed");
+        assert_eq!(config, ExampleConfig::default());
+        let config: ExampleConfig = confy.load_data(None).expect("load_data failed");
+        assert_eq!(config, ExampleConfig::default());
+        assert!(confy.try_load::<ExampleConfig>(None).unwrap().is_none());
+        assert!(!root.path().join("example-app").exists());
+
+        let config = ExampleConfig {
+            name: "Test".to_string(),
+            count: 42,
+        };
+        confy.store(None, &config).expect("store failed");
+        let loaded: ExampleConfig = confy.load(None).expect("load failed");
+        assert_eq!(loaded, config);
+    }
+
+    /// A custom strategy decides where the files go.
+    #[test]
+    fn test_custom_strategy() {
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let file_name = format!("example-config.{}", Format::default().extension());
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .build()
+            .expect("building confy failed");
+        let app_dir = root.path().join("example-app");
+
+        assert_eq!(
+            confy.get_configuration_file_path("example-config").unwrap(),
+            app_dir.join(&file_name)
+        );
+        assert_eq!(
+            confy.data_path("example-config").unwrap(),
+            app_dir.join("data").join(&file_name)
+        );
+        assert_eq!(
+            confy.cache_path("example-config").unwrap(),
+            app_dir.join("cache").join(&file_name)
+        );
+        assert_eq!(
+            confy.state_path("example-config").unwrap(),
+            app_dir.join("state").join(&file_name)
+        );
+
+        let config = ExampleConfig {
+            name: "Custom".to_string(),
+            count: 7,
+        };
+        confy
+            .store("example-config", &config)
+            .expect("store failed");
+        assert!(app_dir.join(&file_name).is_file());
+        let loaded: ExampleConfig = confy.load("example-config").expect("load failed");
+        assert_eq!(loaded, config);
+
+        let unmounted = Confy::builder()
+            .app_name("unmounted-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .build()
+            .expect("building confy failed");
+        assert!(matches!(
+            unmounted.load::<ExampleConfig>(None),
+            Err(ConfyError::BadConfigDirectory(_))
+        ));
+    }
+
+    /// [`ConfyBuilder::build`] needs an application name and an enabled format.
+    #[test]
+    fn test_builder() {
+        assert!(matches!(
+            Confy::builder().build(),
+            Err(ConfyError::MissingAppName)
+        ));
+        assert!(matches!(
+            Confy::builder().app_name("").build(),
+            Err(ConfyError::MissingAppName)
+        ));
+
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .build()
+            .expect("building confy failed");
+        assert_eq!(confy.app_name(), "example-app");
+        assert_eq!(confy.format(), Format::default());
+        assert_eq!(
+            confy.get_configuration_file_path(None).unwrap(),
+            get_configuration_file_path("example-app", None).unwrap()
+        );
+
+        for &format in FORMATS {
+            let built = Confy::builder()
+                .app_name("example-app")
+                .format(format)
+                .build();
+            match built {
+                Ok(confy) => {
+                    assert!(format.is_enabled());
+                    let path = confy.get_configuration_file_path("example-config").unwrap();
+                    assert_eq!(
+                        path.extension().and_then(|ext| ext.to_str()),
+                        Some(format.extension())
+                    );
+                }
+                Err(ConfyError::UnsupportedFormat(f)) => {
+                    assert_eq!(f, format);
+                    assert!(!format.is_enabled());
+                }
+                Err(e) => panic!("unexpected error {e:?}"),
+            }
+        }
+    }
+
+    /// [`store_path_perms`] stores [`ExampleConfig`], with only read permission for owner (UNIX).
+    #[test]
+    #[cfg(unix)]
+    fn test_store_path_perms() {
+        with_config_path(|path| {
+            let config: ExampleConfig = ExampleConfig {
+                name: "Secret".to_string(),
+                count: 16549,
+            };
+            store_path_perms(path, &config, Permissions::from_mode(0o600))
+                .expect("store_path_perms failed");
+            let loaded = load_path(path).expect("load_path failed");
+            assert_eq!(config, loaded);
+        })
+    }
+
+    /// [`store_path_perms`] stores [`ExampleConfig`], as read-only.
+    #[test]
+    fn test_store_path_perms_readonly() {
+        with_config_path(|path| {
+            let config: ExampleConfig = ExampleConfig {
+                name: "Soon read-only".to_string(),
+                count: 27115,
+            };
+            store_path(path, &config).expect("store_path failed");
+
+            let metadata = fs::metadata(path).expect("reading metadata failed");
+            let mut permissions = metadata.permissions();
+            permissions.set_readonly(true);
+
+            store_path_perms(path, &config, permissions).expect("store_path_perms failed");
+
+            assert!(
+                fs::metadata(path)
+                    .expect("reading metadata failed")
+                    .permissions()
+                    .readonly()
+            );
+        })
+    }
+
+    /// [`store_path`] fails when given a root path.
+    #[test]
+    fn test_store_path_root_error() {
+        let err = store_path(PathBuf::from("/"), ExampleConfig::default())
+            .expect_err("store_path should fail");
+        assert_eq!(
+            err.to_string(),
+            r#"Bad configuration directory: "/" is a root or prefix"#,
+        )
+    }
+
+    struct CannotSerialize;
+
+    impl Serialize for CannotSerialize {
+        fn serialize<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            use serde::ser::Error;
+            Err(S::Error::custom("cannot serialize CannotSerialize"))
+        }
+    }
+
+    /// [`watch_path`] reloads a changed file once it settles, and reports broken ones.
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_watch_path() {
+        use std::sync::mpsc;
+
+        with_config_path(|path| {
+            let (sender, receiver) = mpsc::channel();
+            let _watcher = watch_path(path, move |cfg: Result<ExampleConfig, ConfyError>| {
+                let _ = sender.send(cfg);
+            })
+            .expect("watch_path failed");
+            let timeout = std::time::Duration::from_secs(10);
+
+            // several writes in a row, like an editor saving, make a single reload
+            for count in 0..5 {
+                let config = ExampleConfig {
+                    name: "Test".to_string(),
+                    count,
+                };
+                store_path(path, config).expect("store_path failed");
+            }
+            let loaded = receiver.recv_timeout(timeout).expect("no reload");
+            assert_eq!(loaded.expect("reload failed").count, 4);
+            assert!(receiver.recv_timeout(watch::DEBOUNCE * 3).is_err());
+
+            let broken = "some normal text";
+            fs::write(path, broken).unwrap();
+            let error = receiver.recv_timeout(timeout).expect("no reload");
+            let expected = Format::default()
+                .deserialize::<ExampleConfig>(broken.as_bytes())
+                .unwrap_err();
+            assert_eq!(error.unwrap_err().to_string(), expected.to_string());
+        })
+    }
+
+    /// [`LiveConfig`] follows changes on disk and its own stores, and tells subscribers.
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_live_path() {
+        use std::sync::mpsc;
+        use std::time::{Duration, Instant};
+
+        with_config_path(|path| {
+            let live: LiveConfig<ExampleConfig> = live_path(path).expect("live_path failed");
+            assert_eq!(*live.get(), ExampleConfig::default());
+            let (sender, receiver) = mpsc::channel();
+            let sender = Mutex::new(sender);
+            live.subscribe(move |old, new| {
+                let _ = sender.lock().unwrap().send((old.count, new.count));
+            });
+            let timeout = Duration::from_secs(10);
+            let config = |count| ExampleConfig {
+                name: "Test".to_string(),
+                count,
+            };
+
+            store_path(path, config(5)).expect("store_path failed");
+            assert_eq!(receiver.recv_timeout(timeout), Ok((0, 5)));
+            assert_eq!(*live.get(), config(5));
+
+            // own stores take effect at once and are not reloaded
+            live.clone().store(config(7)).expect("store failed");
+            assert_eq!(*live.get(), config(7));
+            assert_eq!(receiver.try_recv(), Ok((5, 7)));
+            assert!(receiver.recv_timeout(watch::DEBOUNCE * 3).is_err());
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded, config(7));
+
+            // once changed elsewhere, the stored value is reloaded when it comes back
+            store_path(path, config(8)).expect("store_path failed");
+            assert_eq!(receiver.recv_timeout(timeout), Ok((7, 8)));
+            store_path(path, config(7)).expect("store_path failed");
+            assert_eq!(receiver.recv_timeout(timeout), Ok((8, 7)));
+            assert_eq!(*live.get(), config(7));
+
+            // a broken file keeps the current value
+            fs::write(path, "some normal text").unwrap();
+            let deadline = Instant::now() + timeout;
+            while live.take_error().is_none() {
+                assert!(
+                    Instant::now() < deadline,
+                    "the broken file was not reported"
+                );
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(*live.get(), config(7));
+        })
+    }
+
+    /// [`Confy::live`] migrates the file and records the version when storing.
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_builder_live_migrations() {
+        if Format::default().has_typed_structs() {
+            return;
+        }
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .migrations(example_migrations(&runs))
+            .build()
+            .expect("building confy failed");
+        let path = confy.get_configuration_file_path(None).unwrap();
+        let version = || recorded_version(&path);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = serde_json::json!({ "title": "Test", "amount": 42 });
+        fs::write(&path, Format::default().serialize(&file).unwrap()).unwrap();
+        let live: LiveConfig<ExampleConfig> = confy.live(None).expect("live failed");
+        assert_eq!(live.get().count, 42);
+        assert_eq!(version(), Some(3));
+
+        live.store(ExampleConfig::default()).expect("store failed");
+        assert_eq!(version(), Some(3));
+        assert_eq!(*runs.lock().unwrap(), [2, 3]);
+    }
+
+    /// Verify that if you call store_path() with an object that fails to serialize,
+    /// the file on disk will not be overwritten or truncated.
+    #[test]
+    fn test_store_path_serialize_error() -> Result<(), ConfyError> {
+        let tmp = tempfile::NamedTempFile::new().expect("Failed to create NamedTempFile");
+        let path = tmp.path();
+        let message = "Hello world!";
+
+        // Write to file.
+        {
+            let mut f = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+                .map_err(ConfyError::OpenConfigurationFileError)?;
+
+            f.write_all(message.as_bytes())
+                .map_err(ConfyError::WriteConfigurationFileError)?;
+
+            f.flush().map_err(ConfyError::WriteConfigurationFileError)?;
+        }
+
+        // Call store_path() to overwrite file with an object that fails to serialize.
+        let store_result = store_path(path, CannotSerialize);
+        assert!(store_result.is_err());
+
+        // Ensure file was not overwritten.
+        let buf = {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .open(path)
+                .map_err(ConfyError::OpenConfigurationFileError)?;
+
+            let mut buf = String::new();
+
+            use std::io::Read;
+            f.read_to_string(&mut buf)
                 .map_err(ConfyError::ReadConfigurationFileError)?;
             buf
         };
@@ -927,6 +2473,1107 @@ mod tests {
         Ok(())
     }
 
+    /// [`update_path`] changes the stored configuration, starting from the default.
+    #[test]
+    fn test_update_path() {
+        with_config_path(|path| {
+            let updated: ExampleConfig = update_path(path, |cfg: &mut ExampleConfig| {
+                assert_eq!(cfg, &ExampleConfig::default());
+                cfg.count += 1;
+            })
+            .expect("update_path failed");
+            assert_eq!(updated.count, 1);
+
+            update_path(path, |cfg: &mut ExampleConfig| cfg.count += 1)
+                .expect("update_path failed");
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded.count, 2);
+            assert!(lock::lock_path(path).is_file());
+        })
+    }
+
+    /// Concurrent updates do not lose each other's changes.
+    #[test]
+    fn test_update_path_concurrent() {
+        with_config_path(|path| {
+            std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| {
+                        for _ in 0..25 {
+                            update_path(path, |cfg: &mut ExampleConfig| cfg.count += 1)
+                                .expect("update_path failed");
+                        }
+                    });
+                }
+            });
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded.count, 100);
+        })
+    }
+
+    /// Waiting for a lock held elsewhere gives up after the timeout.
+    #[test]
+    fn test_lock_timeout() {
+        with_config_path(|path| {
+            store_path(path, ExampleConfig::default()).expect("store_path failed");
+            let timeout = std::time::Duration::from_millis(50);
+
+            let shared = FileLock::shared(path).expect("locking failed");
+            let other = FileLock::shared_within(path, timeout).expect("locking failed");
+            assert!(matches!(
+                FileLock::exclusive_within(path, timeout),
+                Err(ConfyError::LockTimeout(_))
+            ));
+            drop(shared);
+            drop(other);
+
+            let _exclusive = FileLock::exclusive(path).expect("locking failed");
+            assert!(matches!(
+                FileLock::shared_within(path, timeout),
+                Err(ConfyError::LockTimeout(lock_path)) if lock_path == lock::lock_path(path)
+            ));
+        })
+    }
+
+    /// [`load_or_else_quarantine`] moves broken files aside and reports why they broke.
+    #[test]
+    fn test_load_or_else_quarantine() {
+        with_config_path(|path| {
+            let the_value = || ExampleConfig {
+                name: "a".to_string(),
+                count: 5,
+            };
+            let broken = "some normal text";
+
+            // a missing file is simply created
+            let (config, quarantined) =
+                load_or_else_quarantine(path, the_value).expect("load_or_else_quarantine failed");
+            assert_eq!(config, the_value());
+            assert!(quarantined.is_none());
+
+            let mut quarantined_paths = Vec::new();
+            for _ in 0..2 {
+                fs::write(path, broken).unwrap();
+                let (config, quarantined) = load_or_else_quarantine(path, the_value)
+                    .expect("load_or_else_quarantine failed");
+                assert_eq!(config, the_value());
+                let quarantined = quarantined.expect("the broken file was not quarantined");
+
+                let name = quarantined.path().file_name().unwrap().to_string_lossy();
+                assert!(name.starts_with(&format!(
+                    "{}.broken-",
+                    path.file_name().unwrap().to_string_lossy()
+                )));
+                assert_eq!(fs::read_to_string(quarantined.path()).unwrap(), broken);
+                let expected = Format::default()
+                    .deserialize::<ExampleConfig>(broken.as_bytes())
+                    .unwrap_err();
+                assert_eq!(quarantined.error().to_string(), expected.to_string());
+                quarantined_paths.push(quarantined.path().to_path_buf());
+            }
+            assert_ne!(quarantined_paths[0], quarantined_paths[1]);
+
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded, the_value());
+            let (_, quarantined) = load_or_else_quarantine(path, ExampleConfig::default)
+                .expect("load_or_else_quarantine failed");
+            assert!(quarantined.is_none());
+        })
+    }
+
+    /// [`store_path_backups`] keeps the given number of previous versions.
+    #[test]
+    fn test_store_path_backups() {
+        with_config_path(|path| {
+            let config = |count| ExampleConfig {
+                name: "Backup".to_string(),
+                count,
+            };
+            for count in 0..5 {
+                store_path_backups(path, config(count), 3).expect("store_path_backups failed");
+            }
+
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded, config(4));
+            for generation in 1..=3 {
+                let backup = backup_path(path, generation);
+                let loaded: ExampleConfig =
+                    load_path_format(&backup, Format::default()).expect("load_path failed");
+                assert_eq!(loaded, config(4 - generation));
+            }
+            assert!(!backup_path(path, 4).exists());
+
+            // plain stores do not touch the backups
+            store_path(path, config(5)).expect("store_path failed");
+            let loaded: ExampleConfig =
+                load_path_format(backup_path(path, 1), Format::default()).expect("load failed");
+            assert_eq!(loaded, config(3));
+
+            // fewer backups than before drop the older ones
+            store_path_backups(path, config(6), 1).expect("store_path_backups failed");
+            let loaded: ExampleConfig =
+                load_path_format(backup_path(path, 1), Format::default()).expect("load failed");
+            assert_eq!(loaded, config(5));
+            assert!(!backup_path(path, 2).exists());
+            assert!(!backup_path(path, 3).exists());
+            assert!(matches!(
+                restore_backup(path, 2),
+                Err(ConfyError::BackupNotFound(_))
+            ));
+        })
+    }
+
+    /// [`restore_backup`] puts a backup back in place and leaves the backups alone.
+    #[test]
+    fn test_restore_backup() {
+        with_config_path(|path| {
+            let config = |count| ExampleConfig {
+                name: "Restore".to_string(),
+                count,
+            };
+            for count in 0..3 {
+                store_path_backups(path, config(count), 2).expect("store_path_backups failed");
+            }
+
+            restore_backup(path, 2).expect("restore_backup failed");
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded, config(0));
+
+            restore_backup(path, 1).expect("restore_backup failed");
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded, config(1));
+
+            assert!(matches!(
+                restore_backup(path, 3),
+                Err(ConfyError::BackupNotFound(backup)) if backup == backup_path(path, 3)
+            ));
+        })
+    }
+
+    /// Set for the child process of [`test_store_path_atomic`], holds the path to write.
+    const CRASH_TEST_PATH: &str = "CONFY_CRASH_TEST_PATH";
+
+    /// Two configurations big enough that writing them takes a while.
+    fn crash_test_configs() -> [ExampleConfig; 2] {
+        [
+            ExampleConfig {
+                name: "a".repeat(1 << 18),
+                count: 1,
+            },
+            ExampleConfig {
+                name: "b".repeat(1 << 18),
+                count: 2,
+            },
+        ]
+    }
+
+    /// Not a test on its own, [`test_store_path_atomic`] runs it in a child
+    /// process which stores configurations until it is killed.
+    #[test]
+    fn store_path_crash_child() {
+        let Some(path) = std::env::var_os(CRASH_TEST_PATH) else {
+            return;
+        };
+        let configs = crash_test_configs();
+        loop {
+            for config in &configs {
+                store_path(&path, config).expect("store_path failed");
+                // leave the reading test a chance to take the lock
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Kills the child process when dropped, also if the test fails.
+    struct KillOnDrop(std::process::Child);
+
+    impl Drop for KillOnDrop {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// Kill a process in the middle of storing and verify that the file on
+    /// disk always holds one complete configuration, both while the process
+    /// is writing and after it died.
+    #[test]
+    fn test_store_path_atomic() {
+        let configs = crash_test_configs();
+        let assert_complete = |path: &Path| {
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert!(configs.contains(&loaded), "found a partial configuration");
+        };
+        with_config_path(|path| {
+            store_path(path, &configs[0]).expect("store_path failed");
+
+            for round in 0..5 {
+                let child = std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["--exact", "tests::store_path_crash_child", "--quiet"])
+                    .env(CRASH_TEST_PATH, path)
+                    .stdout(std::process::Stdio::null())
+                    .spawn()
+                    .expect("starting child process failed");
+                let child = KillOnDrop(child);
+                let deadline =
+                    std::time::Instant::now() + std::time::Duration::from_millis(100 + 20 * round);
+                while std::time::Instant::now() < deadline {
+                    assert_complete(path);
+                }
+                drop(child);
+                assert_complete(path);
+            }
+        })
+    }
+
+    /// Without explicit permissions, storing keeps those of the existing file.
+    #[test]
+    #[cfg(unix)]
+    fn test_store_path_keeps_permissions() {
+        with_config_path(|path| {
+            let config = ExampleConfig::default();
+            store_path(path, &config).expect("store_path failed");
+            fs::set_permissions(path, Permissions::from_mode(0o640)).unwrap();
+
+            store_path(path, &config).expect("store_path failed");
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+
+            store_path_perms(path, &config, Permissions::from_mode(0o600))
+                .expect("store_path_perms failed");
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        })
+    }
+
+    /// Storing through a symbolic link replaces the file it points to.
+    #[test]
+    #[cfg(unix)]
+    fn test_store_path_symlink() {
+        with_config_path(|path| {
+            let target = path
+                .with_file_name("target")
+                .with_extension(Format::default().extension());
+            let config = ExampleConfig {
+                name: "linked".to_string(),
+                count: 3,
+            };
+            store_path(&target, ExampleConfig::default()).expect("store_path failed");
+            std::os::unix::fs::symlink(&target, path).unwrap();
+
+            store_path(path, &config).expect("store_path failed");
+            assert!(fs::symlink_metadata(path).unwrap().file_type().is_symlink());
+            let loaded: ExampleConfig = load_path(&target).expect("load_path failed");
+            assert_eq!(loaded, config);
+        })
+    }
+
+    /// [`store_path_format`] and [`load_path_format`] round-trip [`ExampleConfig`] in every enabled format.
+    #[test]
+    fn test_store_path_format() {
+        for &format in FORMATS {
+            if !format.is_enabled() {
+                continue;
+            }
+            with_config_path(|path| {
+                let config = ExampleConfig {
+                    name: "Formatted".to_string(),
+                    count: 7,
+                };
+                store_path_format(path, &config, format).expect("store_path_format failed");
+                let loaded = load_path_format(path, format).expect("load_path_format failed");
+                assert_eq!(config, loaded);
+            })
+        }
+    }
+
+    /// [`store_path_format`] fails for formats whose feature is disabled.
+    #[test]
+    #[cfg(not(feature = "ron_conf"))]
+    fn test_store_path_format_unsupported() {
+        with_config_path(|path| {
+            let err = store_path_format(path, ExampleConfig::default(), Format::Ron)
+                .expect_err("store_path_format should fail");
+            assert!(matches!(err, ConfyError::UnsupportedFormat(Format::Ron)));
+            assert!(!path.exists());
+        })
+    }
+
+    /// [`store_path`] and [`load_path`] pick the format from the file extension.
+    #[test]
+    fn test_store_path_detects_format() {
+        for &format in FORMATS {
+            if !format.is_enabled() {
+                continue;
+            }
+            with_config_path(|path| {
+                let path = path.with_extension(format.extension());
+                let config = ExampleConfig {
+                    name: "Detected".to_string(),
+                    count: 3,
+                };
+                store_path(&path, &config).expect("store_path failed");
+
+                let written = fs::read(&path).expect("reading config failed");
+                let parsed: ExampleConfig = format.deserialize(&written).expect("wrong format");
+                assert_eq!(config, parsed);
+
+                let loaded: ExampleConfig = load_path(&path).expect("load_path failed");
+                assert_eq!(config, loaded);
+            })
+        }
+    }
+
+    /// JSON configuration files are written pretty-printed.
+    #[test]
+    #[cfg(feature = "json_conf")]
+    fn test_store_path_json_pretty() {
+        with_config_path(|path| {
+            let path = path.with_extension("json");
+            store_path(&path, ExampleConfig::default()).expect("store_path failed");
+            let written = fs::read_to_string(&path).expect("reading config failed");
+            assert_eq!(written, "{\n  \"name\": \"\",\n  \"count\": 0\n}");
+        })
+    }
+
+    /// JSON5 configuration files may contain comments and trailing commas.
+    #[test]
+    #[cfg(feature = "json5_conf")]
+    fn test_load_path_json5_comments() {
+        with_config_path(|path| {
+            let path = path.with_extension("jsonc");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(
+                &path,
+                "{\n  // who we are\n  \"name\": \"commented\",\n  /* how many */ count: 2,\n}\n",
+            )
+            .expect("writing config failed");
+
+            let loaded: ExampleConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(
+                loaded,
+                ExampleConfig {
+                    name: "commented".to_string(),
+                    count: 2,
+                }
+            );
+        })
+    }
+
+    #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+    struct SectionedConfig {
+        name: String,
+        verbose: bool,
+        nickname: Option<String>,
+        server: ServerSection,
+    }
+
+    #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+    struct ServerSection {
+        host: String,
+        port: u16,
+        ratio: f64,
+    }
+
+    /// [`load_path_merged`] fills in missing fields and reports unknown ones.
+    #[test]
+    fn test_load_path_merged() {
+        with_config_path(|path| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let file = serde_json::json!({
+                "name": "Test",
+                "nmae": "typo",
+                "server": { "host": "example.org", "prot": 8080 },
+            });
+            fs::write(path, Format::default().serialize(&file).unwrap()).unwrap();
+            assert!(load_path::<SectionedConfig>(path).is_err());
+
+            let merged: Merged<SectionedConfig> =
+                load_path_merged(path).expect("load_path_merged failed");
+            assert_eq!(merged.unknown_keys(), ["nmae", "server.prot"]);
+            assert_eq!(merged.server.host, "example.org");
+            assert_eq!(
+                merged.into_inner(),
+                SectionedConfig {
+                    name: "Test".to_string(),
+                    server: ServerSection {
+                        host: "example.org".to_string(),
+                        ..ServerSection::default()
+                    },
+                    ..SectionedConfig::default()
+                }
+            );
+
+            let file = serde_json::json!({ "server": { "port": "not a number" } });
+            fs::write(path, Format::default().serialize(&file).unwrap()).unwrap();
+            assert!(matches!(
+                load_path_merged::<SectionedConfig>(path),
+                Err(ConfyError::DefaultMergeError(_))
+            ));
+
+            fs::remove_file(path).unwrap();
+            let merged: Merged<ExampleConfig> =
+                load_path_merged(path).expect("load_path_merged failed");
+            assert!(merged.unknown_keys().is_empty());
+            assert_eq!(*merged, ExampleConfig::default());
+            let loaded: ExampleConfig = load_path(path).expect("load_path failed");
+            assert_eq!(loaded, ExampleConfig::default());
+        })
+    }
+
+    /// [`upgrade_path`] adds missing fields and keeps everything else in place.
+    #[test]
+    fn test_upgrade_path() {
+        with_config_path(|path| {
+            assert!(!upgrade_path::<ExampleConfig>(path).expect("upgrade_path failed"));
+            assert!(!path.parent().unwrap().exists());
+            // unknown keys are dropped, see test_upgrade_path_ron
+            if Format::default().has_typed_structs() {
+                return;
+            }
+
+            #[derive(Serialize)]
+            struct OldConfig {
+                extra: &'static str,
+                count: usize,
+            }
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let file = OldConfig {
+                extra: "kept",
+                count: 3,
+            };
+            fs::write(path, Format::default().serialize(&file).unwrap()).unwrap();
+            assert!(upgrade_path::<ExampleConfig>(path).expect("upgrade_path failed"));
+
+            #[derive(PartialEq, Debug, Deserialize)]
+            #[serde(deny_unknown_fields)]
+            struct UpgradedConfig {
+                extra: String,
+                count: usize,
+                name: String,
+            }
+
+            let bytes = fs::read(path).unwrap();
+            let upgraded: UpgradedConfig = Format::default().deserialize(&bytes).unwrap();
+            assert_eq!(
+                upgraded,
+                UpgradedConfig {
+                    extra: "kept".to_string(),
+                    count: 3,
+                    name: String::new(),
+                }
+            );
+            let Ok(value::Tree::Map(entries)) = Format::default().deserialize(&bytes) else {
+                panic!("upgraded file is not a map");
+            };
+            let keys: Vec<_> = entries.keys().collect();
+            // the `toml` crate reads tables sorted without its preserve_order
+            // feature, KDL and INI are written sorted
+            if cfg!(feature = "toml_conf") || matches!(Format::default(), Format::Kdl | Format::Ini)
+            {
+                assert_eq!(keys, ["count", "extra", "name"]);
+            } else {
+                assert_eq!(keys, ["extra", "count", "name"]);
+            }
+            assert!(!upgrade_path::<ExampleConfig>(path).expect("upgrade_path failed"));
+        })
+    }
+
+    /// [`upgrade_path`] keeps TOML datetimes.
+    #[test]
+    #[cfg(feature = "toml_conf")]
+    fn test_upgrade_path_datetime() {
+        with_config_path(|path| {
+            let path = path.with_extension("toml");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "count = 3\nwhen = 1979-05-27T07:32:00Z\n").unwrap();
+            assert!(upgrade_path::<ExampleConfig>(&path).expect("upgrade_path failed"));
+
+            let contents = fs::read_to_string(&path).unwrap();
+            let table: toml::Table = toml::from_str(&contents).unwrap();
+            assert!(table["when"].is_datetime(), "{contents}");
+            assert_eq!(table["name"].as_str(), Some(""));
+        })
+    }
+
+    /// [`upgrade_path`] writes RON files as structs.
+    #[test]
+    #[cfg(feature = "ron_conf")]
+    fn test_upgrade_path_ron() {
+        with_config_path(|path| {
+            let path = path.with_extension("ron");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "(count: 3)").unwrap();
+            assert!(upgrade_path::<ExampleConfig>(&path).expect("upgrade_path failed"));
+
+            let config: ExampleConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(
+                config,
+                ExampleConfig {
+                    name: String::new(),
+                    count: 3,
+                }
+            );
+        })
+    }
+
+    /// [`ConfyBuilder::write_back_defaults`] adds nested fields when loading.
+    #[test]
+    fn test_builder_write_back_defaults() {
+        let root = tempfile::tempdir().expect("creating test fixture failed");
+        let confy = Confy::builder()
+            .app_name("example-app")
+            .strategy(ConfigStrategy::Custom(Box::new(RootLocator(
+                root.path().to_path_buf(),
+            ))))
+            .write_back_defaults(true)
+            .build()
+            .expect("building confy failed");
+        assert!(confy.writes_back_defaults());
+
+        let path = confy.get_configuration_file_path(None).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = serde_json::json!({ "name": "Test", "server": { "host": "example.org" } });
+        fs::write(&path, Format::default().serialize(&file).unwrap()).unwrap();
+
+        let merged: Merged<SectionedConfig> = confy.load_merged(None).expect("load_merged failed");
+        assert_eq!(merged.name, "Test");
+        // every field is in the file now, read like the configuration is
+        let written: SectionedConfig = Format::default()
+            .deserialize(&fs::read(&path).unwrap())
+            .unwrap();
+        assert_eq!(
+            written,
+            SectionedConfig {
+                name: "Test".to_string(),
+                server: ServerSection {
+                    host: "example.org".to_string(),
+                    ..ServerSection::default()
+                },
+                ..SectionedConfig::default()
+            }
+        );
+
+        let loaded: SectionedConfig = confy.load(None).expect("load failed");
+        assert_eq!(loaded, merged.into_inner());
+    }
+
+    fn env_vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Environment variables override fields according to their type, nested ones through `__`.
+    #[test]
+    fn test_env_overrides() {
+        let config = env::apply_overrides(
+            SectionedConfig::default(),
+            "MYAPP",
+            env_vars(&[
+                ("MYAPP_NAME", "from env"),
+                ("MYAPP_VERBOSE", "true"),
+                ("MYAPP_NICKNAME", "42"),
+                ("MYAPP_SERVER__HOST", "example.org"),
+                ("MYAPP_SERVER__PORT", "8080"),
+                ("MYAPP_server__Ratio", "0.25"),
+                ("OTHERAPP_NAME", "ignored"),
+                ("MYAPP_SERVER__", "ignored"),
+            ]),
+        )
+        .expect("apply_overrides failed");
+
+        assert_eq!(
+            config,
+            SectionedConfig {
+                name: "from env".to_string(),
+                verbose: true,
+                nickname: Some("42".to_string()),
+                server: ServerSection {
+                    host: "example.org".to_string(),
+                    port: 8080,
+                    ratio: 0.25,
+                },
+            }
+        );
+    }
+
+    /// Fields without a matching environment variable keep the values from the file.
+    #[test]
+    fn test_load_path_with_env_keeps_file_values() {
+        with_config_path(|path| {
+            let stored = SectionedConfig {
+                name: "from file".to_string(),
+                server: ServerSection {
+                    host: "localhost".to_string(),
+                    port: 1234,
+                    ratio: 1.5,
+                },
+                ..SectionedConfig::default()
+            };
+            store_path(path, &stored).expect("store_path failed");
+
+            let loaded: SectionedConfig = load_path(path).expect("load_path failed");
+            let config = env::apply_overrides(
+                loaded,
+                "CONFY_TEST",
+                env_vars(&[("CONFY_TEST_SERVER__PORT", "4321")]),
+            )
+            .expect("apply_overrides failed");
+            assert_eq!(
+                config,
+                SectionedConfig {
+                    server: ServerSection {
+                        port: 4321,
+                        ..stored.server
+                    },
+                    ..stored
+                }
+            );
+        })
+    }
+
+    /// Variables naming no field are ignored, even with `deny_unknown_fields`.
+    #[test]
+    fn test_env_overrides_unknown_fields() {
+        #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct StrictConfig {
+            name: String,
+            server: ServerSection,
+        }
+
+        let config = env::apply_overrides(
+            StrictConfig::default(),
+            "MYAPP",
+            env_vars(&[
+                ("MYAPP_CONFIG", "/etc/myapp.toml"),
+                ("MYAPP_NAME__FIRST", "ignored"),
+                ("MYAPP_SERVER__HOSTNAME", "ignored"),
+                ("MYAPP_SERVER__PORT", "8080"),
+            ]),
+        )
+        .expect("apply_overrides failed");
+        assert_eq!(config.name, "");
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.server.host, "");
+    }
+
+    /// Values that do not parse into the type of their field are reported.
+    #[test]
+    fn test_env_overrides_bad_value() {
+        let err = env::apply_overrides(
+            SectionedConfig::default(),
+            "MYAPP",
+            env_vars(&[("MYAPP_SERVER__PORT", "eighty")]),
+        )
+        .expect_err("apply_overrides should fail");
+        assert!(matches!(err, ConfyError::EnvironmentOverrideError(_)));
+    }
+
+    /// Variables below an optional struct that is `None` need all of its required fields.
+    #[test]
+    fn test_env_overrides_unset_parent() {
+        #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+        struct OptionalServer {
+            name: String,
+            server: Option<ServerSection>,
+        }
+
+        let err = env::apply_overrides(
+            OptionalServer::default(),
+            "MYAPP",
+            env_vars(&[("MYAPP_NAME", "Test"), ("MYAPP_SERVER__PORT", "8080")]),
+        )
+        .expect_err("apply_overrides should fail");
+        let ConfyError::UnsetEnvironmentOverride(vars, _) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!(vars, "MYAPP_SERVER__PORT");
+
+        let config = env::apply_overrides(
+            OptionalServer::default(),
+            "MYAPP",
+            env_vars(&[
+                ("MYAPP_SERVER__HOST", "example.org"),
+                ("MYAPP_SERVER__PORT", "8080"),
+                ("MYAPP_SERVER__RATIO", "0.5"),
+            ]),
+        )
+        .expect("apply_overrides failed");
+        assert_eq!(
+            config.server,
+            Some(ServerSection {
+                host: "example.org".to_string(),
+                port: 8080,
+                ratio: 0.5,
+            })
+        );
+    }
+
+    /// Later layers override earlier ones field by field and missing files are skipped.
+    #[test]
+    fn test_load_layers() {
+        let dir = tempfile::tempdir().expect("creating test fixture failed");
+        let extension = Format::default().extension();
+        let system = dir.path().join("system").with_extension(extension);
+        let user = dir.path().join("user").with_extension(extension);
+        let project = dir.path().join("project").with_extension(extension);
+        let missing = dir.path().join("missing").with_extension(extension);
+        let write = |path: &Path, value: serde_json::Value| {
+            let tree = value::Tree::from(value);
+            fs::write(path, Format::default().serialize(&tree).unwrap()).unwrap();
+        };
+
+        write(
+            &system,
+            serde_json::json!({
+                "name": "system",
+                "server": { "host": "system.example.org", "port": 80 },
+            }),
+        );
+        write(
+            &user,
+            serde_json::json!({ "verbose": true, "server": { "port": 8080 } }),
+        );
+        write(&project, serde_json::json!({ "server": { "ratio": 0.5 } }));
+
+        let layered: Layered<SectionedConfig> = load_layers([
+            Layer::Default,
+            Layer::System(system.clone()),
+            Layer::System(missing),
+            Layer::User(user.clone()),
+            Layer::Project(project.clone()),
+        ])
+        .expect("load_layers failed");
+
+        assert_eq!(
+            *layered,
+            SectionedConfig {
+                name: "system".to_string(),
+                verbose: true,
+                nickname: None,
+                server: ServerSection {
+                    host: "system.example.org".to_string(),
+                    port: 8080,
+                    ratio: 0.5,
+                },
+            }
+        );
+        assert_eq!(layered.source("name"), Some(&Layer::System(system.clone())));
+        assert_eq!(layered.source("verbose"), Some(&Layer::User(user)));
+        assert_eq!(layered.source("nickname"), Some(&Layer::Default));
+        assert_eq!(layered.source("server.host"), Some(&Layer::System(system)));
+        assert_eq!(
+            layered.source("server.ratio"),
+            Some(&Layer::Project(project.clone()))
+        );
+        assert_eq!(layered.source("server"), Some(&Layer::Project(project)));
+        assert_eq!(layered.source("server.missing"), None);
+        assert!(
+            !dir.path()
+                .join("missing")
+                .with_extension(extension)
+                .exists()
+        );
+    }
+
+    /// An empty YAML file, which holds `null`, is skipped like a missing one.
+    #[test]
+    #[cfg(feature = "yaml_conf")]
+    fn test_load_layers_empty_yaml() {
+        let dir = tempfile::tempdir().expect("creating test fixture failed");
+        let empty = dir.path().join(".example-app.yml");
+        fs::write(&empty, "").unwrap();
+
+        let layered: Layered<SectionedConfig> =
+            load_layers([Layer::Default, Layer::Project(empty)]).expect("load_layers failed");
+        assert_eq!(*layered, SectionedConfig::default());
+        assert_eq!(layered.source("name"), Some(&Layer::Default));
+    }
+
+    /// The user layer is the file [`load`] uses, the project layer sits in the current directory.
+    #[test]
+    fn test_layer_paths() {
+        let layers = layer_paths("example-app", None).expect("layer_paths failed");
+        assert_eq!(layers.first(), Some(&Layer::Default));
+        let user = get_configuration_file_path("example-app", None).unwrap();
+        assert!(layers.contains(&Layer::User(user)));
+        let project = std::env::current_dir()
+            .unwrap()
+            .join(format!(".example-app.{}", Format::default().extension()));
+        assert_eq!(layers.last(), Some(&Layer::Project(project)));
+    }
+
+    /// INI files map top-level fields to the general section and nested structs to sections.
+    #[test]
+    #[cfg(feature = "ini_conf")]
+    fn test_store_path_ini_sections() {
+        with_config_path(|path| {
+            let path = path.with_extension("ini");
+            let config = SectionedConfig {
+                name: "legacy".to_string(),
+                verbose: true,
+                nickname: None,
+                server: ServerSection {
+                    host: "localhost".to_string(),
+                    port: 8080,
+                    ratio: 0.5,
+                },
+            };
+            store_path(&path, &config).expect("store_path failed");
+
+            let written = fs::read_to_string(&path).expect("reading config failed");
+            assert_eq!(
+                written,
+                "name=legacy\nverbose=true\n\n[server]\nhost=localhost\nport=8080\nratio=0.5\n"
+            );
+
+            let loaded: SectionedConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(config, loaded);
+        })
+    }
+
+    /// Existing INI files with comments and spacing are read in place.
+    #[test]
+    #[cfg(feature = "ini_conf")]
+    fn test_load_path_ini_legacy() {
+        with_config_path(|path| {
+            let path = path.with_extension("ini");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(
+                &path,
+                "; written by hand\nname = old tool\nverbose = false\nnickname = oldie\n\n[server]\nhost = example.org\nport = 21\nratio = 2\n",
+            )
+            .expect("writing config failed");
+
+            let loaded: SectionedConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(
+                loaded,
+                SectionedConfig {
+                    name: "old tool".to_string(),
+                    verbose: false,
+                    nickname: Some("oldie".to_string()),
+                    server: ServerSection {
+                        host: "example.org".to_string(),
+                        port: 21,
+                        ratio: 2.0,
+                    },
+                }
+            );
+        })
+    }
+
+    /// INI cannot express structs nested more than one level deep.
+    #[test]
+    #[cfg(feature = "ini_conf")]
+    fn test_store_path_ini_too_deep() {
+        #[derive(Serialize)]
+        struct Outer {
+            inner: SectionedConfig,
+        }
+
+        with_config_path(|path| {
+            let path = path.with_extension("ini");
+            let err = store_path(
+                &path,
+                Outer {
+                    inner: SectionedConfig::default(),
+                },
+            )
+            .expect_err("store_path should fail");
+            assert!(matches!(err, ConfyError::IniValueError(_)));
+        })
+    }
+
+    /// INI syntax errors keep their position, unlike values that do not fit the configuration.
+    #[test]
+    #[cfg(feature = "ini_conf")]
+    fn test_load_path_bad_ini() {
+        with_config_path(|path| {
+            let path = path.with_extension("ini");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+            fs::write(&path, "name = Test\n[server").unwrap();
+            let err = load_path::<SectionedConfig>(&path).expect_err("load_path should fail");
+            let ConfyError::BadIniData(err) = err else {
+                panic!("unexpected error: {err:?}");
+            };
+            assert_eq!(err.line, 2);
+
+            fs::write(&path, "name = Test\nverbose = maybe\n").unwrap();
+            let err = load_path::<SectionedConfig>(&path).expect_err("load_path should fail");
+            assert!(matches!(err, ConfyError::IniValueError(_)));
+        })
+    }
+
+    /// Hand-written KDL files are read through [`load_path`] and [`load_or_else`].
+    #[test]
+    #[cfg(feature = "kdl_conf")]
+    fn test_load_path_kdl() {
+        with_config_path(|path| {
+            let path = path.with_extension("kdl");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "// edited by hand\nname \"kdl\"\ncount 12\n")
+                .expect("writing config failed");
+
+            let expected = ExampleConfig {
+                name: "kdl".to_string(),
+                count: 12,
+            };
+            let loaded: ExampleConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(loaded, expected);
+            let loaded: ExampleConfig =
+                load_or_else(&path, ExampleConfig::default).expect("load_or_else failed");
+            assert_eq!(loaded, expected);
+        })
+    }
+
+    /// KDL documents are made of nodes, so only structs and maps can be stored.
+    #[test]
+    #[cfg(feature = "kdl_conf")]
+    fn test_store_path_kdl_not_a_struct() {
+        with_config_path(|path| {
+            let path = path.with_extension("kdl");
+            let err = store_path(&path, vec![1, 2]).expect_err("store_path should fail");
+            let ConfyError::SerializeKdlError(err) = err else {
+                panic!("unexpected error: {err:?}");
+            };
+            assert_eq!(
+                err.to_string(),
+                "only structs and maps can be stored as KDL"
+            );
+        })
+    }
+
+    /// KDL keeps `false` and `None` fields as well as sequences when storing.
+    #[test]
+    #[cfg(feature = "kdl_conf")]
+    fn test_store_path_kdl_round_trip() {
+        #[derive(PartialEq, Default, Debug, Serialize, Deserialize)]
+        struct KdlConfig {
+            enabled: bool,
+            limit: Option<u32>,
+            tags: Vec<String>,
+            empty: Vec<u8>,
+            nested: ExampleConfig,
+            items: Vec<ExampleConfig>,
+        }
+
+        with_config_path(|path| {
+            let path = path.with_extension("kdl");
+            let config = KdlConfig {
+                enabled: false,
+                limit: None,
+                tags: vec!["a".to_string(), "b c".to_string()],
+                empty: vec![],
+                nested: ExampleConfig {
+                    name: "inner".to_string(),
+                    count: 1,
+                },
+                items: vec![ExampleConfig::default()],
+            };
+            store_path(&path, &config).expect("store_path failed");
+            let loaded: KdlConfig = load_path(&path).expect("load_path failed");
+            assert_eq!(config, loaded);
+        })
+    }
+
+    /// Binary formats round-trip data that is not valid UTF-8.
+    #[test]
+    #[cfg(any(feature = "cbor_conf", feature = "msgpack_conf"))]
+    fn test_store_path_binary() {
+        for &format in FORMATS {
+            if !format.is_enabled() || !format.is_binary() {
+                continue;
+            }
+            with_config_path(|path| {
+                let path = path.with_extension(format.extension());
+                let config = ExampleConfig {
+                    name: "binary".to_string(),
+                    count: 255,
+                };
+                store_path(&path, &config).expect("store_path failed");
+                let written = fs::read(&path).expect("reading config failed");
+                assert!(std::str::from_utf8(&written).is_err());
+
+                let loaded: ExampleConfig = load_path(&path).expect("load_path failed");
+                assert_eq!(config, loaded);
+            })
+        }
+    }
+
+    /// Text formats refuse files which are not valid UTF-8.
+    #[test]
+    fn test_load_path_invalid_utf8() {
+        let text_formats = [
+            Format::Toml,
+            Format::Yaml,
+            Format::Ron,
+            Format::Json,
+            Format::Json5,
+            Format::Ini,
+            Format::Kdl,
+        ];
+        // builds with only binary formats have nothing to refuse
+        let Some(format) = text_formats.into_iter().find(|format| format.is_enabled()) else {
+            return;
+        };
+        with_config_path(|path| {
+            let path = path.with_extension(format.extension());
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, [0xff, 0xfe, 0x00]).expect("writing config failed");
+            let err = load_path::<ExampleConfig>(&path).expect_err("load_path should fail");
+            assert!(matches!(err, ConfyError::ReadConfigurationFileError(_)));
+        })
+    }
+
+    /// [`Format::from_path`] maps extensions to formats.
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_extension("TOML"), Some(Format::Toml));
+        assert_eq!(Format::from_extension("yaml"), Some(Format::Yaml));
+        assert_eq!(Format::from_extension("yml"), Some(Format::Yaml));
+        assert_eq!(Format::from_extension("ron"), Some(Format::Ron));
+        assert_eq!(Format::from_extension("json"), Some(Format::Json));
+        assert_eq!(Format::from_extension("json5"), Some(Format::Json5));
+        assert_eq!(Format::from_extension("jsonc"), Some(Format::Json5));
+        assert_eq!(Format::from_extension("ini"), Some(Format::Ini));
+        assert_eq!(Format::from_extension("kdl"), Some(Format::Kdl));
+        assert_eq!(Format::from_extension("cbor"), Some(Format::Cbor));
+        assert_eq!(Format::from_extension("msgpack"), Some(Format::MessagePack));
+        assert_eq!(Format::from_extension("conf"), None);
+
+        assert_eq!(Format::from_path("config").unwrap(), Format::default());
+        assert!(matches!(
+            Format::from_path("config.conf"),
+            Err(ConfyError::UnknownExtension(ext)) if ext == "conf"
+        ));
+    }
+
+    /// [`load_path`] refuses unknown extensions without creating a file.
+    #[test]
+    fn test_load_path_unknown_extension() {
+        with_config_path(|path| {
+            let path = path.with_extension("conf");
+            let err = load_path::<ExampleConfig>(&path).expect_err("load_path should fail");
+            assert!(matches!(err, ConfyError::UnknownExtension(_)));
+            assert!(!path.exists());
+        })
+    }
+
+    /// [`load_path`] reports extensions of disabled formats.
+    #[test]
+    #[cfg(not(feature = "ron_conf"))]
+    fn test_load_path_unsupported_extension() {
+        with_config_path(|path| {
+            let path = path.with_extension("ron");
+            let err = load_path::<ExampleConfig>(&path).expect_err("load_path should fail");
+            assert!(matches!(err, ConfyError::UnsupportedFormat(Format::Ron)));
+        })
+    }
+
     // Verify that [`load_path`] can deserialize into structs with differing names
     // as long as they have the same fields
     #[test]
@@ -938,7 +3585,7 @@ mod tests {
                 count: usize,
             }
 
-            store_path(path, &ExampleConfig::default()).expect("store_path failed");
+            store_path(path, ExampleConfig::default()).expect("store_path failed");
             let _: AnotherExampleConfig = load_path(path).expect("load_path failed");
         });
 
@@ -0,0 +1,244 @@
+//! Configuration shared between threads and kept in sync with its file
+
+use crate::lock::FileLock;
+use crate::migrate::{self, Migrations};
+use crate::watch::{Watcher, watch_changes};
+use crate::{
+    Confy, ConfyError, Format, create_config_dir, load_path_format, try_read_path,
+    write_bytes_locked,
+};
+use arc_swap::ArcSwap;
+use serde::{Serialize, de::DeserializeOwned};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+type Subscriber<T> = Arc<dyn Fn(&T, &T) + Send + Sync>;
+
+/// A configuration that follows its file, readable from any thread.
+///
+/// [`LiveConfig::get`] is cheap, it hands out the current value without
+/// touching the file. When the file changes on disk, it is reloaded like
+/// [`watch`] does and the new value replaces the old one, which is then
+/// passed to every subscriber together with the new one. Storing through
+/// [`LiveConfig::store`] replaces the value right away, without waiting for
+/// the file to be reloaded.
+///
+/// Clones share the value and the subscribers. The file is watched until the
+/// last clone is dropped. Requires the `watch` feature.
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     verbose: bool,
+/// }
+///
+/// let live = confy::live::<MyConfig>("my-app-name", None)?;
+/// live.subscribe(|old, new| {
+///     if old.verbose != new.verbose {
+///         println!("verbose is now {}", new.verbose);
+///     }
+/// });
+///
+/// let worker = live.clone();
+/// std::thread::spawn(move || {
+///     if worker.get().verbose {
+///         println!("working hard");
+///     }
+/// });
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`watch`]: fn.watch.html
+pub struct LiveConfig<T> {
+    shared: Arc<Shared<T>>,
+    _watcher: Arc<Watcher>,
+}
+
+struct Shared<T> {
+    path: PathBuf,
+    format: Format,
+    backups: usize,
+    migrations: Option<Migrations>,
+    current: ArcSwap<T>,
+    subscribers: Mutex<Vec<Subscriber<T>>>,
+    /// What was stored through the handle last, to skip reloading it.
+    written: Mutex<Option<Vec<u8>>>,
+    error: Mutex<Option<ConfyError>>,
+}
+
+/// Load an application configuration and keep it in sync with its file
+///
+/// The configuration is loaded like [`load`] does, creating the file with
+/// default values if it does not exist. See [`LiveConfig`] for how it is
+/// kept up to date.
+///
+/// [`load`]: fn.load.html
+pub fn live<'a, T>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+) -> Result<LiveConfig<T>, ConfyError>
+where
+    T: Serialize + DeserializeOwned + Default + Send + Sync + 'static,
+{
+    Confy::global(app_name).live(config_name)
+}
+
+/// Load an application configuration from a specified path and keep it in sync with the file
+///
+/// Like [`load_path`], the format is determined from the file extension. For
+/// more information on errors and behavior, see [`live`]'s documentation.
+///
+/// [`load_path`]: fn.load_path.html
+/// [`live`]: fn.live.html
+pub fn live_path<T>(path: impl AsRef<Path>) -> Result<LiveConfig<T>, ConfyError>
+where
+    T: Serialize + DeserializeOwned + Default + Send + Sync + 'static,
+{
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    let cfg = load_path_format(path, format)?;
+    LiveConfig::start(path.to_path_buf(), format, 0, None, cfg)
+}
+
+impl<T> LiveConfig<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Start watching the file at `path`, which currently holds `cfg`.
+    pub(crate) fn start(
+        path: PathBuf,
+        format: Format,
+        backups: usize,
+        migrations: Option<Migrations>,
+        cfg: T,
+    ) -> Result<Self, ConfyError> {
+        let shared = Arc::new(Shared {
+            path,
+            format,
+            backups,
+            migrations,
+            current: ArcSwap::from_pointee(cfg),
+            subscribers: Mutex::new(Vec::new()),
+            written: Mutex::new(None),
+            error: Mutex::new(None),
+        });
+        let reloading = Arc::clone(&shared);
+        let watcher = watch_changes(&shared.path, move |changed| reloading.reload(changed))?;
+        Ok(LiveConfig {
+            shared,
+            _watcher: Arc::new(watcher),
+        })
+    }
+
+    /// The current configuration.
+    pub fn get(&self) -> Arc<T> {
+        self.shared.current.load_full()
+    }
+
+    /// Call `subscriber` with the old and the new configuration whenever it changes.
+    ///
+    /// Subscribers run on the thread that replaced the value, which for
+    /// changes on disk is the background thread watching the file.
+    pub fn subscribe(&self, subscriber: impl Fn(&T, &T) + Send + Sync + 'static) {
+        lock(&self.shared.subscribers).push(Arc::new(subscriber));
+    }
+
+    /// Store a new configuration and make it the current one
+    ///
+    /// The file is written like [`store_path`] does, and the value is
+    /// replaced at once instead of being reloaded from the file.
+    ///
+    /// [`store_path`]: fn.store_path.html
+    pub fn store(&self, cfg: T) -> Result<(), ConfyError> {
+        let shared = &self.shared;
+        let bytes = migrate::serialize(&cfg, shared.format, shared.migrations.as_ref())?;
+        create_config_dir(&shared.path)?;
+        {
+            let _lock = FileLock::exclusive(&shared.path)?;
+            write_bytes_locked(&shared.path, &bytes, None, shared.backups)?;
+            // still under the lock, so the reload of this write sees it
+            *lock(&shared.written) = Some(bytes);
+        }
+        shared.replace(cfg);
+        Ok(())
+    }
+
+    /// Take the error the last reload failed with, if any.
+    ///
+    /// A file that cannot be read or parsed leaves the current configuration
+    /// in place, the error is kept here instead.
+    pub fn take_error(&self) -> Option<ConfyError> {
+        lock(&self.shared.error).take()
+    }
+
+    /// The path of the configuration file.
+    pub fn path(&self) -> &Path {
+        &self.shared.path
+    }
+}
+
+impl<T> Shared<T> {
+    fn reload(&self, changed: Result<(), ConfyError>)
+    where
+        T: DeserializeOwned,
+    {
+        let bytes = match changed.and_then(|()| try_read_path(&self.path)) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return,
+            Err(e) => return self.fail(e),
+        };
+        {
+            let mut written = lock(&self.written);
+            if written.as_deref() == Some(&bytes[..]) {
+                return;
+            }
+            // changed elsewhere, so the stored bytes coming back are a change as well
+            *written = None;
+        }
+        match migrate::deserialize(&bytes, self.format, self.migrations.as_ref()) {
+            Ok(cfg) => self.replace(cfg),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn replace(&self, cfg: T) {
+        let new = Arc::new(cfg);
+        let old = self.current.swap(Arc::clone(&new));
+        // called without holding the lock, so subscribers may subscribe or store
+        let subscribers = lock(&self.subscribers).clone();
+        for subscriber in subscribers {
+            subscriber(&old, &new);
+        }
+    }
+
+    fn fail(&self, error: ConfyError) {
+        *lock(&self.error) = Some(error);
+    }
+}
+
+impl<T> Clone for LiveConfig<T> {
+    fn clone(&self) -> Self {
+        LiveConfig {
+            shared: Arc::clone(&self.shared),
+            _watcher: Arc::clone(&self._watcher),
+        }
+    }
+}
+
+impl<T> fmt::Debug for LiveConfig<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiveConfig")
+            .field("path", &self.shared.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lock `mutex`, a panicking subscriber leaves nothing half done.
+fn lock<U>(mutex: &Mutex<U>) -> MutexGuard<'_, U> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,82 @@
+//! Relocating the configuration file through environment variables
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Where a configuration file is, together with what decided that.
+///
+/// Returned by [`Confy::config_location`].
+///
+/// [`Confy::config_location`]: struct.Confy.html#method.config_location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigLocation {
+    path: PathBuf,
+    source: LocationSource,
+}
+
+/// What decided the location of a configuration file, see [`ConfigLocation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LocationSource {
+    /// The path was derived from the [`ConfigStrategy`](crate::ConfigStrategy).
+    Strategy,
+    /// The environment variable with this name, `<PREFIX>_CONFIG`, named the file.
+    EnvFile(String),
+    /// The environment variable with this name, `<PREFIX>_CONFIG_DIR`, named the directory.
+    EnvDir(String),
+}
+
+impl ConfigLocation {
+    pub(crate) fn new(path: PathBuf, source: LocationSource) -> Self {
+        ConfigLocation { path, source }
+    }
+
+    /// The path of the configuration file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What decided the path.
+    pub fn source(&self) -> &LocationSource {
+        &self.source
+    }
+
+    /// Whether an environment variable overrode the path the strategy would have used.
+    pub fn is_override(&self) -> bool {
+        !matches!(self.source, LocationSource::Strategy)
+    }
+
+    /// Take the path of the configuration file.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+}
+
+/// Look for an override of the configuration file location.
+///
+/// `<prefix>_CONFIG` names the file of the default configuration, the one
+/// without a `config_name`. `<prefix>_CONFIG_DIR` names the directory all
+/// configuration files are placed in, `file_name` is appended to it. Empty
+/// variables are ignored.
+pub(crate) fn from_env(
+    prefix: &str,
+    config_name: Option<&str>,
+    file_name: &str,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Option<ConfigLocation> {
+    let var = |name: String| {
+        let value = var(&name).filter(|value| !value.is_empty())?;
+        Some((name, PathBuf::from(value)))
+    };
+
+    if config_name.is_none()
+        && let Some((name, path)) = var(format!("{prefix}_CONFIG"))
+    {
+        return Some(ConfigLocation::new(path, LocationSource::EnvFile(name)));
+    }
+    let (name, dir) = var(format!("{prefix}_CONFIG_DIR"))?;
+    Some(ConfigLocation::new(
+        dir.join(file_name),
+        LocationSource::EnvDir(name),
+    ))
+}
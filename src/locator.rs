@@ -0,0 +1,52 @@
+//! User supplied directory layouts
+
+use crate::ConfyError;
+use std::path::PathBuf;
+
+/// Decides where the files of an application go, for [`ConfigStrategy::Custom`].
+///
+/// Only [`ConfigLocator::config_dir`] has to be implemented. The other
+/// directories default to subdirectories of it, named `data`, `cache` and
+/// `state`. Failures, like a managed root that is not mounted, are best
+/// reported as [`ConfyError::BadConfigDirectory`].
+///
+/// ```rust,no_run
+/// # use confy::{ConfigLocator, ConfigStrategy, Confy, ConfyError};
+/// # use std::path::PathBuf;
+/// struct Corporate;
+///
+/// impl ConfigLocator for Corporate {
+///     fn config_dir(&self, app_name: &str) -> Result<PathBuf, ConfyError> {
+///         Ok(PathBuf::from("/opt/acme/config").join(app_name))
+///     }
+/// }
+///
+/// # fn main() -> Result<(), ConfyError> {
+/// let confy = Confy::builder()
+///     .app_name("my-app-name")
+///     .strategy(ConfigStrategy::Custom(Box::new(Corporate)))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ConfigStrategy::Custom`]: enum.ConfigStrategy.html#variant.Custom
+pub trait ConfigLocator: Send + Sync {
+    /// The directory the configuration files of `app_name` are placed in.
+    fn config_dir(&self, app_name: &str) -> Result<PathBuf, ConfyError>;
+
+    /// The directory the data files of `app_name` are placed in.
+    fn data_dir(&self, app_name: &str) -> Result<PathBuf, ConfyError> {
+        self.config_dir(app_name).map(|dir| dir.join("data"))
+    }
+
+    /// The directory the cache files of `app_name` are placed in.
+    fn cache_dir(&self, app_name: &str) -> Result<PathBuf, ConfyError> {
+        self.config_dir(app_name).map(|dir| dir.join("cache"))
+    }
+
+    /// The directory the state files of `app_name` are placed in.
+    fn state_dir(&self, app_name: &str) -> Result<PathBuf, ConfyError> {
+        self.config_dir(app_name).map(|dir| dir.join("state"))
+    }
+}
@@ -0,0 +1,111 @@
+//! Advisory locking of configuration files between processes
+//!
+//! Files are stored by renaming a new file over them, so the lock is taken on
+//! a sidecar file named like the configuration file with `.lock` appended,
+//! which stays in place.
+
+use crate::ConfyError;
+use crate::utils::resolve_symlink;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to release a lock.
+pub(crate) const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to sleep between attempts to take a lock.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A lock on a configuration file, released when dropped.
+pub(crate) struct FileLock {
+    _file: Option<File>,
+}
+
+impl FileLock {
+    /// Take a shared lock on `path`, for reading it.
+    ///
+    /// Reading must keep working where the lock file cannot be created, like
+    /// in a read-only system directory or before the directory exists, so
+    /// the file is read without a lock then.
+    pub(crate) fn shared(path: &Path) -> Result<FileLock, ConfyError> {
+        FileLock::shared_within(path, LOCK_TIMEOUT)
+    }
+
+    /// Take an exclusive lock on `path`, for writing it.
+    pub(crate) fn exclusive(path: &Path) -> Result<FileLock, ConfyError> {
+        FileLock::exclusive_within(path, LOCK_TIMEOUT)
+    }
+
+    /// Take a shared lock on `path` only if its lock file exists already.
+    ///
+    /// For reading without leaving anything behind. Stores replace the file
+    /// atomically, so reading it unlocked never sees half of a write.
+    pub(crate) fn shared_existing(path: &Path) -> Result<FileLock, ConfyError> {
+        let lock_path = lock_path(path);
+        let file = match OpenOptions::new().read(true).write(true).open(&lock_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(FileLock { _file: None }),
+        };
+        acquire(file, &lock_path, File::try_lock_shared, LOCK_TIMEOUT)
+    }
+
+    pub(crate) fn shared_within(path: &Path, timeout: Duration) -> Result<FileLock, ConfyError> {
+        let lock_path = lock_path(path);
+        let file = match open(&lock_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(FileLock { _file: None }),
+        };
+        acquire(file, &lock_path, File::try_lock_shared, timeout)
+    }
+
+    pub(crate) fn exclusive_within(path: &Path, timeout: Duration) -> Result<FileLock, ConfyError> {
+        let lock_path = lock_path(path);
+        let file = open(&lock_path).map_err(ConfyError::LockFileError)?;
+        acquire(file, &lock_path, File::try_lock, timeout)
+    }
+}
+
+fn open(lock_path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path)
+}
+
+fn acquire(
+    file: File,
+    lock_path: &Path,
+    try_lock: fn(&File) -> Result<(), TryLockError>,
+    timeout: Duration,
+) -> Result<FileLock, ConfyError> {
+    let start = Instant::now();
+    loop {
+        match try_lock(&file) {
+            Ok(()) => return Ok(FileLock { _file: Some(file) }),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                thread::sleep(RETRY_INTERVAL)
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(ConfyError::LockTimeout(lock_path.to_path_buf()));
+            }
+            // some file systems cannot lock at all
+            Err(TryLockError::Error(e)) if e.kind() == ErrorKind::Unsupported => {
+                return Ok(FileLock { _file: None });
+            }
+            Err(TryLockError::Error(e)) => return Err(ConfyError::LockFileError(e)),
+        }
+    }
+}
+
+/// The lock file belonging to `path`, next to the file a symbolic link points to.
+pub(crate) fn lock_path(path: &Path) -> PathBuf {
+    let path = resolve_symlink(path);
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".lock");
+    path.with_file_name(name)
+}
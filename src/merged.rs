@@ -0,0 +1,321 @@
+//! Loading files that lack newer fields or carry unknown ones
+
+use crate::lock::FileLock;
+use crate::migrate::{self, Migrations};
+use crate::value::{Lenient, Tree, merge};
+use crate::{Confy, ConfyError, Format, create_config_dir, read_bytes_locked, write_bytes_locked};
+use indexmap::IndexMap;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_ignored::Path as IgnoredPath;
+use serde_json::Value;
+use std::ops::Deref;
+use std::path::Path;
+
+/// A configuration completed with default values, see [`load_merged`].
+///
+/// Dereferences to the configuration itself.
+///
+/// [`load_merged`]: fn.load_merged.html
+#[derive(Debug, Clone)]
+pub struct Merged<T> {
+    config: T,
+    unknown: Vec<String>,
+}
+
+impl<T> Merged<T> {
+    /// The keys in the file which the configuration has no field for, sorted.
+    ///
+    /// Nested fields are addressed with dots, like `server.prot`, and
+    /// elements of lists with their index.
+    pub fn unknown_keys(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Take the merged configuration.
+    pub fn into_inner(self) -> T {
+        self.config
+    }
+}
+
+impl<T> Deref for Merged<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.config
+    }
+}
+
+/// Load an application configuration, taking missing fields from `Default`
+///
+/// Like [`load`], but the file is deep-merged over `T::default()` first, so
+/// a file written before a field was added still loads, with the default
+/// value for the new field, even without `#[serde(default)]`. Keys the
+/// configuration has no field for are collected in
+/// [`Merged::unknown_keys`] instead of being dropped silently, so they can
+/// be reported as likely typos. Values of the wrong type fail with
+/// [`ConfyError::DefaultMergeError`].
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     theme: String,
+///     added_later: bool,
+/// }
+///
+/// let cfg = confy::load_merged::<MyConfig>("my-app-name", None)?;
+/// for key in cfg.unknown_keys() {
+///     eprintln!("warning: unknown setting {key}");
+/// }
+/// println!("theme: {}", cfg.theme);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`load`]: fn.load.html
+pub fn load_merged<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+) -> Result<Merged<T>, ConfyError> {
+    Confy::global(app_name).load_merged(config_name)
+}
+
+/// Load an application configuration from a specified path, taking missing fields from `Default`
+///
+/// Like [`load_path`], the format is determined from the file extension. For
+/// more information on errors and behavior, see [`load_merged`]'s
+/// documentation.
+///
+/// [`load_path`]: fn.load_path.html
+/// [`load_merged`]: fn.load_merged.html
+pub fn load_path_merged<T: Serialize + DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+) -> Result<Merged<T>, ConfyError> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    load_path_merged_format(path, format, true, None, 0)
+}
+
+/// Load the configuration at `path` merged over its default values, creating
+/// the file with them if it is missing and `create` is set.
+///
+/// With `migrations`, an outdated file is upgraded and stored again first,
+/// keeping up to `backups` previous versions. Without `create`, it is only
+/// upgraded in memory.
+pub(crate) fn load_path_merged_format<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+    format: Format,
+    create: bool,
+    migrations: Option<&Migrations>,
+    backups: usize,
+) -> Result<Merged<T>, ConfyError> {
+    let mut merged = load_migrated_merged(path, format, create, migrations, backups)?;
+    if let Some(migrations) = migrations {
+        // the version belongs in the file, even without a field for it
+        merged.unknown.retain(|key| key != migrations.field());
+    }
+    Ok(merged)
+}
+
+fn load_migrated_merged<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+    format: Format,
+    create: bool,
+    migrations: Option<&Migrations>,
+    backups: usize,
+) -> Result<Merged<T>, ConfyError> {
+    let bytes = {
+        let _lock = FileLock::shared(path)?;
+        read_bytes_locked(path)?
+    };
+    match bytes {
+        Some(bytes) => {
+            let file: Value = format.deserialize(&bytes)?;
+            let outdated = match migrations {
+                Some(migrations) => migrations.version_of(&file)? != migrations.version(),
+                None => false,
+            };
+            if !outdated {
+                return merge_over_default(file);
+            }
+            if let (Some(migrations), false) = (migrations, create) {
+                let mut file = file;
+                migrations.migrate(&mut file)?;
+                return merge_over_default(file);
+            }
+        }
+        None if !create => {
+            return Ok(Merged {
+                config: T::default(),
+                unknown: Vec::new(),
+            });
+        }
+        None => {}
+    }
+
+    create_config_dir(path)?;
+    let _lock = FileLock::exclusive(path)?;
+    // another process may have created or migrated the file in the meantime
+    let Some(bytes) = read_bytes_locked(path)? else {
+        let config = T::default();
+        let bytes = migrate::serialize(&config, format, migrations)?;
+        write_bytes_locked(path, &bytes, None, 0)?;
+        let config = match migrations {
+            // with the version recorded in it, if it has a field for that
+            Some(_) => format.deserialize(&bytes)?,
+            None => config,
+        };
+        return Ok(Merged {
+            config,
+            unknown: Vec::new(),
+        });
+    };
+    let mut file: Value = format.deserialize(&bytes)?;
+    let Some(migrations) = migrations else {
+        return merge_over_default(file);
+    };
+    if !migrations.migrate(&mut file)? {
+        return merge_over_default(file);
+    }
+    let merged = merge_over_default(file.clone())?;
+    let bytes = migrations.serialize_migrated(file, &merged.config, format)?;
+    write_bytes_locked(path, &bytes, None, backups)?;
+    Ok(merged)
+}
+
+/// Add the fields missing from a configuration file with their default values
+///
+/// Fields added to the configuration struct after the file was written are
+/// stored in it with the values from `T::default()`, so users can discover
+/// them. Everything already in the file, including keys the struct does not
+/// know, keeps its value and its place, new fields are appended. Comments
+/// and formatting are not kept, as the file is written anew, atomically and
+/// under an exclusive lock like [`store_path`] does. RON files are written
+/// from the configuration struct and lose the keys it does not know. KDL and
+/// INI files are written with sorted keys, as are TOML files read by the
+/// `toml` crate without its `preserve_order` feature.
+///
+/// Returns whether the file was changed. A missing file is left alone.
+/// To do this on every load, see [`ConfyBuilder::write_back_defaults`].
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     theme: String,
+///     #[serde(default)]
+///     added_later: bool,
+/// }
+///
+/// let cfg: MyConfig = confy::load_path("my-config.toml")?;
+/// if confy::upgrade_path::<MyConfig>("my-config.toml")? {
+///     println!("new settings were added to my-config.toml");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`store_path`]: fn.store_path.html
+/// [`ConfyBuilder::write_back_defaults`]: struct.ConfyBuilder.html#method.write_back_defaults
+pub fn upgrade_path<T: Serialize + DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+) -> Result<bool, ConfyError> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    upgrade_path_format::<T>(path, format, 0)
+}
+
+/// Add the default values missing from the file at `path`, keeping up to
+/// `backups` previous versions if it changes.
+pub(crate) fn upgrade_path_format<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+    format: Format,
+    backups: usize,
+) -> Result<bool, ConfyError> {
+    // without creating a lock file next to a file that is not there
+    if !path.exists() {
+        return Ok(false);
+    }
+    let _lock = FileLock::exclusive(path)?;
+    let Some(bytes) = read_bytes_locked(path)? else {
+        return Ok(false);
+    };
+    let file: Tree = format.deserialize(&bytes)?;
+    let defaults: Tree = format.round_trip(&T::default())?;
+    let upgraded = with_defaults(&file, defaults);
+    if upgraded == file {
+        return Ok(false);
+    }
+    let bytes = if format.has_typed_structs() {
+        let cfg: T = T::deserialize(Lenient(Value::from(upgraded)))
+            .map_err(ConfyError::DefaultMergeError)?;
+        format.serialize(&cfg)?
+    } else {
+        format.serialize(&upgraded)?
+    };
+    write_bytes_locked(path, &bytes, None, backups)?;
+    Ok(true)
+}
+
+/// `file` with the entries of `defaults` it lacks appended, at every level.
+fn with_defaults(file: &Tree, defaults: Tree) -> Tree {
+    let (Tree::Map(file_entries), Tree::Map(mut default_entries)) = (file, defaults) else {
+        return file.clone();
+    };
+    let mut entries: IndexMap<String, Tree> = file_entries
+        .iter()
+        .map(|(key, value)| {
+            let value = match default_entries.shift_remove(key) {
+                Some(default) => with_defaults(value, default),
+                None => value.clone(),
+            };
+            (key.clone(), value)
+        })
+        .collect();
+    entries.append(&mut default_entries);
+    Tree::Map(entries)
+}
+
+fn merge_over_default<T: Serialize + DeserializeOwned + Default>(
+    file: Value,
+) -> Result<Merged<T>, ConfyError> {
+    let mut merged = serde_json::to_value(T::default()).map_err(ConfyError::DefaultMergeError)?;
+    merge(&mut merged, file);
+
+    let mut unknown = Vec::new();
+    let config = serde_ignored::deserialize(Lenient(merged), |path| {
+        let mut key = String::new();
+        push_key(&path, &mut key);
+        unknown.push(key);
+    })
+    .map_err(ConfyError::DefaultMergeError)?;
+    unknown.sort();
+    Ok(Merged { config, unknown })
+}
+
+/// Append `path` to `key` as dotted key, like `server.port`.
+fn push_key(path: &IgnoredPath, key: &mut String) {
+    let segment = match path {
+        IgnoredPath::Root => return,
+        IgnoredPath::Seq { parent, index } => {
+            push_key(parent, key);
+            index.to_string()
+        }
+        IgnoredPath::Map { parent, key: name } => {
+            push_key(parent, key);
+            name.clone()
+        }
+        // options and newtypes do not show up in the file
+        IgnoredPath::Some { parent }
+        | IgnoredPath::NewtypeStruct { parent }
+        | IgnoredPath::NewtypeVariant { parent } => return push_key(parent, key),
+    };
+    if !key.is_empty() {
+        key.push('.');
+    }
+    key.push_str(&segment);
+}
@@ -0,0 +1,487 @@
+//! Upgrading configuration files written for older versions of a struct
+
+use crate::lock::FileLock;
+use crate::value::{Lenient, Tree};
+use crate::{Confy, ConfyError, Format, create_config_dir, read_bytes_locked, write_bytes_locked};
+use serde::ser::{self, Serializer};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::cell::Cell;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+type Migration = Arc<dyn Fn(&mut Value) + Send + Sync>;
+
+/// A chain of upgrades from older shapes of a configuration to the current one.
+///
+/// Every configuration file carries its version in a field, `version` unless
+/// changed with [`Migrations::version_field`]. Files without it, or with
+/// version `0`, are version `1`, the shape before migrations were introduced.
+/// The first migration
+/// added upgrades version `1` to `2`, the second one `2` to `3` and so on,
+/// so the current version is one more than the number of migrations.
+///
+/// Migrations work on the untyped contents of the file, the same for every
+/// format, so they keep working when the struct they were written for is
+/// long gone. The configuration struct should either have the version field
+/// as well or accept unknown fields. Store it through a [`Confy`] handle
+/// built with [`ConfyBuilder::migrations`], which records the current
+/// version, so the next load does not migrate it again. RON files are
+/// written from the struct, so with RON it needs the version field itself,
+/// storing without it fails with [`ConfyError::VersionFieldMissing`].
+///
+/// ```rust,no_run
+/// # use confy::{ConfyError, Migrations};
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     version: u64,
+///     server: Server,
+/// }
+///
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct Server {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// let migrations = Migrations::new()
+///     // version 2 renamed `host` to `server`
+///     .migration(|cfg| {
+///         if let Some(host) = cfg.as_object_mut().and_then(|cfg| cfg.remove("host")) {
+///             cfg["server"] = host;
+///         }
+///     })
+///     // version 3 moved `server` into a table of its own
+///     .migration(|cfg| {
+///         let host = cfg["server"].take();
+///         cfg["server"] = serde_json::json!({ "host": host, "port": 80 });
+///     });
+///
+/// let cfg: MyConfig = confy::load_migrated("my-app-name", None, &migrations)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ConfyBuilder::migrations`]: struct.ConfyBuilder.html#method.migrations
+#[derive(Clone)]
+pub struct Migrations {
+    field: String,
+    steps: Vec<Migration>,
+}
+
+impl Migrations {
+    /// No migrations yet, with the version kept in the field `version`.
+    pub fn new() -> Self {
+        Migrations {
+            field: "version".to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Keep the version in the top-level field `field` instead.
+    pub fn version_field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    /// Add the migration to the next version.
+    pub fn migration(mut self, migration: impl Fn(&mut Value) + Send + Sync + 'static) -> Self {
+        self.steps.push(Arc::new(migration));
+        self
+    }
+
+    /// The top-level field the version is kept in.
+    pub(crate) fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// The current version, which configurations are upgraded to.
+    pub fn version(&self) -> u64 {
+        self.steps.len() as u64 + 1
+    }
+
+    /// The version of the configuration in `value`.
+    pub(crate) fn version_of(&self, value: &Value) -> Result<u64, ConfyError> {
+        let version = match value.get(&self.field) {
+            None => return Ok(1),
+            Some(Value::Number(n)) => n.as_u64(),
+            // formats like INI only know strings
+            Some(Value::String(s)) => s.trim().parse().ok(),
+            Some(_) => None,
+        };
+        match version {
+            Some(version) if version > self.version() => {
+                Err(ConfyError::VersionTooNew(version, self.version()))
+            }
+            Some(version) => Ok(version.max(1)),
+            None => Err(ConfyError::BadVersion(value[&self.field].to_string())),
+        }
+    }
+
+    /// Upgrade `value` to the current version, returning whether anything changed.
+    pub(crate) fn migrate(&self, value: &mut Value) -> Result<bool, ConfyError> {
+        let version = self.version_of(value)?;
+        if version == self.version() {
+            return Ok(false);
+        }
+        for migration in &self.steps[version as usize - 1..] {
+            migration(value);
+        }
+        self.stamp(value);
+        Ok(true)
+    }
+
+    /// Record the current version in `value`.
+    fn stamp(&self, value: &mut Value) {
+        if let Value::Object(entries) = value {
+            entries.insert(self.field.clone(), self.version().into());
+        }
+    }
+
+    /// Deserialize `bytes`, upgrading them first if they are outdated.
+    ///
+    /// Unlike [`load_path_migrated`], the file is not changed.
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+        format: Format,
+    ) -> Result<T, ConfyError> {
+        let mut value: Value = format.deserialize(bytes)?;
+        if !self.migrate(&mut value)? {
+            return format.deserialize(bytes);
+        }
+        T::deserialize(Lenient(value)).map_err(ConfyError::MigrationError)
+    }
+
+    /// Serialize `cfg` with the current version recorded.
+    pub(crate) fn serialize<T: Serialize>(
+        &self,
+        cfg: &T,
+        format: Format,
+    ) -> Result<Vec<u8>, ConfyError> {
+        if format.has_typed_structs() {
+            let found = Cell::new(false);
+            let stamped = Stamped {
+                cfg,
+                field: &self.field,
+                version: self.version(),
+                found: &found,
+            };
+            let bytes = format.serialize(&stamped);
+            if !found.get() {
+                return Err(ConfyError::VersionFieldMissing(self.field.clone()));
+            }
+            return bytes;
+        }
+        let mut value: Value = format.round_trip(cfg)?;
+        self.stamp(&mut value);
+        format.serialize(&Tree::from(value))
+    }
+
+    /// Serialize the migrated `value` of `cfg`.
+    ///
+    /// `value` is stored as is, keeping fields the configuration lacks, unless
+    /// [`Format::has_typed_structs`].
+    pub(crate) fn serialize_migrated<T: Serialize>(
+        &self,
+        value: Value,
+        cfg: &T,
+        format: Format,
+    ) -> Result<Vec<u8>, ConfyError> {
+        if format.has_typed_structs() {
+            self.serialize(cfg, format)
+        } else {
+            format.serialize(&Tree::from(value))
+        }
+    }
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Migrations::new()
+    }
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrations")
+            .field("field", &self.field)
+            .field("version", &self.version())
+            .finish()
+    }
+}
+
+/// Load an application configuration, upgrading it from older versions first
+///
+/// Like [`load`], but a file written for an older version of the
+/// configuration is passed through the missing [`Migrations`] before it is
+/// deserialized, and the upgraded file is stored again. A file written for a
+/// newer version than the migrations know fails with
+/// [`ConfyError::VersionTooNew`], so an older program never mangles it. New
+/// files are created with the current version.
+///
+/// [`load`]: fn.load.html
+pub fn load_migrated<'a, T: Serialize + DeserializeOwned + Default>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+    migrations: &Migrations,
+) -> Result<T, ConfyError> {
+    Confy::global(app_name)
+        .with_migrations(migrations.clone())
+        .load(config_name)
+}
+
+/// Load an application configuration from a specified path, upgrading it from older versions first
+///
+/// Like [`load_path`], the format is determined from the file extension. For
+/// more information on errors and behavior, see [`load_migrated`]'s
+/// documentation.
+///
+/// [`load_path`]: fn.load_path.html
+/// [`load_migrated`]: fn.load_migrated.html
+pub fn load_path_migrated<T: Serialize + DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+    migrations: &Migrations,
+) -> Result<T, ConfyError> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    load_path_migrated_format(path, format, migrations, 0, true)
+}
+
+/// Load the configuration at `path`, migrating and storing it again if it is
+/// outdated. The previous file is kept as one of `backups` backups.
+///
+/// Without `create`, nothing is written: a missing file loads the defaults
+/// and an outdated one is only migrated in memory.
+pub(crate) fn load_path_migrated_format<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+    format: Format,
+    migrations: &Migrations,
+    backups: usize,
+    create: bool,
+) -> Result<T, ConfyError> {
+    {
+        let _lock = FileLock::shared(path)?;
+        match read_bytes_locked(path)? {
+            Some(bytes) => {
+                let value: Value = format.deserialize(&bytes)?;
+                if migrations.version_of(&value)? == migrations.version() {
+                    return format.deserialize(&bytes);
+                }
+                if !create {
+                    return migrations.deserialize(&bytes, format);
+                }
+            }
+            None if !create => return Ok(T::default()),
+            None => {}
+        }
+    }
+
+    create_config_dir(path)?;
+    let _lock = FileLock::exclusive(path)?;
+    // another process may have created or migrated the file in the meantime
+    let Some(bytes) = read_bytes_locked(path)? else {
+        let bytes = migrations.serialize(&T::default(), format)?;
+        write_bytes_locked(path, &bytes, None, 0)?;
+        // with the version recorded in it, if it has a field for that
+        return format.deserialize(&bytes);
+    };
+    let mut value: Value = format.deserialize(&bytes)?;
+    if !migrations.migrate(&mut value)? {
+        return format.deserialize(&bytes);
+    }
+    let cfg = T::deserialize(Lenient(value.clone())).map_err(ConfyError::MigrationError)?;
+    let bytes = migrations.serialize_migrated(value, &cfg, format)?;
+    write_bytes_locked(path, &bytes, None, backups)?;
+    Ok(cfg)
+}
+
+/// Deserialize `bytes`, upgrading them with `migrations` if there are any.
+pub(crate) fn deserialize<T: DeserializeOwned>(
+    bytes: &[u8],
+    format: Format,
+    migrations: Option<&Migrations>,
+) -> Result<T, ConfyError> {
+    match migrations {
+        Some(migrations) => migrations.deserialize(bytes, format),
+        None => format.deserialize(bytes),
+    }
+}
+
+/// Serialize `cfg`, recording the current version if there are `migrations`.
+pub(crate) fn serialize<T: Serialize>(
+    cfg: &T,
+    format: Format,
+    migrations: Option<&Migrations>,
+) -> Result<Vec<u8>, ConfyError> {
+    match migrations {
+        Some(migrations) => migrations.serialize(cfg, format),
+        None => format.serialize(cfg),
+    }
+}
+
+/// Serializes a configuration struct with the value of its version field
+/// replaced, for formats which cannot write it from a [`Value`].
+struct Stamped<'a, T> {
+    cfg: &'a T,
+    field: &'a str,
+    version: u64,
+    /// Whether the struct had the version field.
+    found: &'a Cell<bool>,
+}
+
+impl<T: Serialize> Serialize for Stamped<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.cfg.serialize(StampSerializer {
+            inner: serializer,
+            field: self.field,
+            version: self.version,
+            found: self.found,
+        })
+    }
+}
+
+/// Passes everything on to `inner`, except for the version field of the
+/// outermost struct.
+struct StampSerializer<'a, S> {
+    inner: S,
+    field: &'a str,
+    version: u64,
+    found: &'a Cell<bool>,
+}
+
+macro_rules! forward_serialize {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $ret:ident,)*) => {$(
+        fn $method(self, $($arg: $ty),*) -> Result<Self::$ret, S::Error> {
+            self.inner.$method($($arg),*)
+        }
+    )*};
+}
+
+impl<'a, S: Serializer> Serializer for StampSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = S::SerializeSeq;
+    type SerializeTuple = S::SerializeTuple;
+    type SerializeTupleStruct = S::SerializeTupleStruct;
+    type SerializeTupleVariant = S::SerializeTupleVariant;
+    type SerializeMap = S::SerializeMap;
+    type SerializeStruct = StampStruct<'a, S::SerializeStruct>;
+    type SerializeStructVariant = S::SerializeStructVariant;
+
+    forward_serialize! {
+        serialize_bool(v: bool) -> Ok,
+        serialize_i8(v: i8) -> Ok,
+        serialize_i16(v: i16) -> Ok,
+        serialize_i32(v: i32) -> Ok,
+        serialize_i64(v: i64) -> Ok,
+        serialize_i128(v: i128) -> Ok,
+        serialize_u8(v: u8) -> Ok,
+        serialize_u16(v: u16) -> Ok,
+        serialize_u32(v: u32) -> Ok,
+        serialize_u64(v: u64) -> Ok,
+        serialize_u128(v: u128) -> Ok,
+        serialize_f32(v: f32) -> Ok,
+        serialize_f64(v: f64) -> Ok,
+        serialize_char(v: char) -> Ok,
+        serialize_str(v: &str) -> Ok,
+        serialize_bytes(v: &[u8]) -> Ok,
+        serialize_none() -> Ok,
+        serialize_unit() -> Ok,
+        serialize_unit_struct(name: &'static str) -> Ok,
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str) -> Ok,
+        serialize_seq(len: Option<usize>) -> SerializeSeq,
+        serialize_tuple(len: usize) -> SerializeTuple,
+        serialize_tuple_struct(name: &'static str, len: usize) -> SerializeTupleStruct,
+        serialize_tuple_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> SerializeTupleVariant,
+        serialize_map(len: Option<usize>) -> SerializeMap,
+        serialize_struct_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> SerializeStructVariant,
+    }
+
+    fn serialize_some<U: Serialize + ?Sized>(self, value: &U) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_some(value)
+    }
+
+    fn serialize_newtype_struct<U: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &U,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_struct(name, value)
+    }
+
+    fn serialize_newtype_variant<U: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &U,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_newtype_variant(name, index, variant, value)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(StampStruct {
+            inner: self.inner.serialize_struct(name, len)?,
+            field: self.field,
+            version: self.version,
+            found: self.found,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// The outermost struct of a [`StampSerializer`].
+struct StampStruct<'a, S> {
+    inner: S,
+    field: &'a str,
+    version: u64,
+    found: &'a Cell<bool>,
+}
+
+impl<S: ser::SerializeStruct> ser::SerializeStruct for StampStruct<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<U: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &U,
+    ) -> Result<(), S::Error> {
+        if key == self.field {
+            self.found.set(true);
+            self.inner.serialize_field(key, &self.version)
+        } else {
+            self.inner.serialize_field(key, value)
+        }
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
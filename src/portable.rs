@@ -0,0 +1,86 @@
+//! Keeping all files next to the executable
+
+use crate::ConfyError;
+use etcetera::AppStrategy;
+use std::path::{Path, PathBuf};
+
+/// The directory layout of [`ConfigStrategy::Portable`](crate::ConfigStrategy::Portable).
+///
+/// Everything lives below the directory of the executable, in `config`,
+/// `data`, `cache` and `state` subdirectories, each with a directory for the
+/// application, so several applications can share the directory.
+pub(crate) struct Portable {
+    root: PathBuf,
+    app_name: String,
+}
+
+impl Portable {
+    /// Use the directory the running executable is in.
+    pub(crate) fn new(app_name: &str) -> Result<Self, ConfyError> {
+        exe_dir().map(|dir| Portable::in_dir(dir, app_name))
+    }
+
+    pub(crate) fn in_dir(root: impl Into<PathBuf>, app_name: &str) -> Self {
+        Portable {
+            root: root.into(),
+            app_name: app_name.to_string(),
+        }
+    }
+
+    fn app_dir(&self, kind: &str) -> PathBuf {
+        self.root.join(kind).join(&self.app_name)
+    }
+
+    /// Use the directory of the executable if it contains the marker file of
+    /// the application, `<app_name>.portable`.
+    pub(crate) fn from_marker(app_name: &str) -> Option<Self> {
+        Portable::from_marker_in(exe_dir().ok()?, app_name)
+    }
+
+    pub(crate) fn from_marker_in(dir: impl Into<PathBuf>, app_name: &str) -> Option<Self> {
+        let dir = dir.into();
+        dir.join(marker_name(app_name))
+            .is_file()
+            .then(|| Portable::in_dir(dir, app_name))
+    }
+}
+
+/// The name of the file which turns on portable mode for `app_name`.
+pub(crate) fn marker_name(app_name: &str) -> String {
+    format!("{app_name}.portable")
+}
+
+fn exe_dir() -> Result<PathBuf, ConfyError> {
+    let exe = std::env::current_exe().map_err(|e| {
+        ConfyError::BadConfigDirectory(format!("could not determine executable path: {e}"))
+    })?;
+    exe.parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| ConfyError::BadConfigDirectory(format!("{exe:?} has no parent directory")))
+}
+
+impl AppStrategy for Portable {
+    fn home_dir(&self) -> &Path {
+        &self.root
+    }
+
+    fn config_dir(&self) -> PathBuf {
+        self.app_dir("config")
+    }
+
+    fn data_dir(&self) -> PathBuf {
+        self.app_dir("data")
+    }
+
+    fn cache_dir(&self) -> PathBuf {
+        self.app_dir("cache")
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        Some(self.app_dir("state"))
+    }
+
+    fn runtime_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
@@ -0,0 +1,63 @@
+//! Moving unparsable configuration files aside
+
+use crate::ConfyError;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A configuration file that could not be parsed and was moved aside.
+///
+/// Returned by [`load_or_else_quarantine`] next to the configuration that
+/// replaced it, so the application can tell the user where their file went.
+///
+/// [`load_or_else_quarantine`]: fn.load_or_else_quarantine.html
+#[derive(Debug)]
+pub struct Quarantined {
+    path: PathBuf,
+    error: ConfyError,
+}
+
+impl Quarantined {
+    /// Where the broken file is now, `<name>.broken-<unix timestamp>`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Why the file could not be parsed, like [`ConfyError::BadTomlData`].
+    pub fn error(&self) -> &ConfyError {
+        &self.error
+    }
+
+    /// Take the error the file failed to parse with.
+    pub fn into_error(self) -> ConfyError {
+        self.error
+    }
+}
+
+/// Rename the file at `path` to `<name>.broken-<unix timestamp>`.
+///
+/// A counter is appended if a file of that name exists already.
+pub(crate) fn quarantine(path: &Path, error: ConfyError) -> Result<Quarantined, ConfyError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(".broken-{timestamp}"));
+
+    let mut broken = path.with_file_name(&name);
+    let mut counter = 0;
+    while broken.exists() {
+        counter += 1;
+        let mut numbered = name.clone();
+        numbered.push(format!("-{counter}"));
+        broken = path.with_file_name(numbered);
+    }
+
+    fs::rename(path, &broken).map_err(ConfyError::QuarantineError)?;
+    Ok(Quarantined {
+        path: broken,
+        error,
+    })
+}
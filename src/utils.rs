@@ -1,16 +1,79 @@
 //! Some storage utilities
 
-use std::fs::File;
-use std::io::{Error as IoError, Read};
+use crate::ConfyError;
+use std::fs::{self, File, Permissions};
+use std::io::{Error as IoError, Read, Write};
+use std::path::{Path, PathBuf};
 
-pub trait CheckedStringRead {
-    fn get_string(&mut self) -> Result<String, IoError>;
+pub trait CheckedRead {
+    fn get_bytes(&mut self) -> Result<Vec<u8>, IoError>;
 }
 
-impl CheckedStringRead for File {
-    fn get_string(&mut self) -> Result<String, IoError> {
-        let mut s = String::new();
-        self.read_to_string(&mut s)?;
-        Ok(s)
+impl CheckedRead for File {
+    fn get_bytes(&mut self) -> Result<Vec<u8>, IoError> {
+        let mut buf = Vec::new();
+        self.read_to_end(&mut buf)?;
+        Ok(buf)
     }
 }
+
+/// The file a symbolic link points to, or `path` itself.
+pub fn resolve_symlink(path: &Path) -> PathBuf {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Replace the contents of `path` without ever leaving a partially written file behind.
+///
+/// The bytes go into a temporary file next to `path`, which is synced to disk
+/// and then renamed over it. `perms` are applied before the rename; without
+/// them the permissions of the file being replaced are kept.
+pub fn write_atomically(
+    path: &Path,
+    bytes: &[u8],
+    perms: Option<Permissions>,
+) -> Result<(), ConfyError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let perms = perms.or_else(|| fs::metadata(path).ok().map(|meta| meta.permissions()));
+
+    let prefix = format!(".{file_name}.");
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(".tmp");
+    // temporary files are private by default, new configuration files get
+    // the usual permissions instead
+    #[cfg(unix)]
+    if perms.is_none() {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(Permissions::from_mode(0o666));
+    }
+    let mut tmp = builder
+        .tempfile_in(dir)
+        .map_err(ConfyError::OpenConfigurationFileError)?;
+
+    tmp.write_all(bytes)
+        .map_err(ConfyError::WriteConfigurationFileError)?;
+    tmp.as_file()
+        .sync_all()
+        .map_err(ConfyError::WriteConfigurationFileError)?;
+    if let Some(perms) = perms {
+        tmp.as_file()
+            .set_permissions(perms)
+            .map_err(ConfyError::SetPermissionsFileError)?;
+    }
+    tmp.persist(path)
+        .map_err(|e| ConfyError::WriteConfigurationFileError(e.error))?;
+
+    // make the rename itself durable, not every platform can sync a directory
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
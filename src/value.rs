@@ -0,0 +1,304 @@
+//! Helpers for working with untyped configuration values
+
+use indexmap::IndexMap;
+use serde::de::{
+    self, Deserialize, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Unexpected, Visitor,
+    value::{MapDeserializer, SeqDeserializer},
+};
+use serde::forward_to_deserialize_any;
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+use serde_json::{Error, Value};
+use std::fmt;
+
+/// How the `toml` crate passes datetimes through serde, as a struct with a
+/// single field holding the datetime as string.
+const DATETIME_STRUCT: &str = "$__toml_private_Datetime";
+const DATETIME_FIELD: &str = "$__toml_private_datetime";
+
+/// Deserializes a [`Value`] like `serde_json` does, except that strings are
+/// parsed into whatever scalar type the target asks for.
+///
+/// Formats which only know strings, like INI, go through this to end up
+/// with the types of the configuration struct.
+pub(crate) struct Lenient(pub(crate) Value);
+
+impl<'de> IntoDeserializer<'de, Error> for Lenient {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::String(s) => match s.trim().parse() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+                },
+                value => value.$method(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Array(values) => {
+                let mut seq = SeqDeserializer::new(values.into_iter().map(Lenient));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Object(entries) => {
+                let mut map =
+                    MapDeserializer::new(entries.into_iter().map(|(k, v)| (k, Lenient(v))));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Number(n) => visitor.visit_string(n.to_string()),
+            Value::Bool(b) => visitor.visit_string(b.to_string()),
+            value => value.deserialize_string(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            value => value.deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// Untyped contents of a configuration file.
+///
+/// Unlike a [`Value`], maps keep the order of their entries, and the entries
+/// are written with plain values before nested maps and lists of maps, as
+/// TOML requires of its tables. Some serializers, like `basic-toml`'s, fail
+/// otherwise. TOML datetimes are written as datetimes again, not as the maps
+/// they are read as.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Tree {
+    Map(IndexMap<String, Tree>),
+    Seq(Vec<Tree>),
+    Leaf(Value),
+}
+
+impl Tree {
+    /// Whether this is written as a table, or a list of them, in TOML.
+    fn is_table(&self) -> bool {
+        match self {
+            Tree::Map(_) => self.datetime().is_none(),
+            Tree::Seq(items) => !items.is_empty() && items.iter().all(Tree::is_table),
+            Tree::Leaf(_) => false,
+        }
+    }
+
+    /// The datetime this is, if it was read from TOML as one.
+    fn datetime(&self) -> Option<&Value> {
+        match self {
+            Tree::Map(entries) if entries.len() == 1 => match entries.get(DATETIME_FIELD) {
+                Some(Tree::Leaf(datetime @ Value::String(_))) => Some(datetime),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<Value> for Tree {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Object(entries) => Tree::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, Tree::from(value)))
+                    .collect(),
+            ),
+            Value::Array(items) => Tree::Seq(items.into_iter().map(Tree::from).collect()),
+            value => Tree::Leaf(value),
+        }
+    }
+}
+
+impl From<Tree> for Value {
+    fn from(tree: Tree) -> Self {
+        match tree {
+            Tree::Map(entries) => Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, tree)| (key, Value::from(tree)))
+                    .collect(),
+            ),
+            Tree::Seq(items) => Value::Array(items.into_iter().map(Value::from).collect()),
+            Tree::Leaf(value) => value,
+        }
+    }
+}
+
+impl Serialize for Tree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(datetime) = self.datetime() {
+            let mut datetime_struct = serializer.serialize_struct(DATETIME_STRUCT, 1)?;
+            datetime_struct.serialize_field(DATETIME_FIELD, datetime)?;
+            return datetime_struct.end();
+        }
+        match self {
+            Tree::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                let (tables, plain): (Vec<_>, Vec<_>) =
+                    entries.iter().partition(|(_, value)| value.is_table());
+                for (key, value) in plain.into_iter().chain(tables) {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Tree::Seq(items) => serializer.collect_seq(items),
+            Tree::Leaf(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Tree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TreeVisitor)
+    }
+}
+
+struct TreeVisitor;
+
+impl<'de> Visitor<'de> for TreeVisitor {
+    type Value = Tree;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any configuration value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::Bool(v)))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::from(v)))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::from(v)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::from(v)))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::from(v)))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::String(v)))
+    }
+
+    fn visit_unit<E>(self) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::Null))
+    }
+
+    fn visit_none<E>(self) -> Result<Tree, E> {
+        Ok(Tree::Leaf(Value::Null))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Tree, D::Error> {
+        Tree::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tree, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Tree::Seq(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Tree, A::Error> {
+        let mut entries = IndexMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            entries.insert(key, value);
+        }
+        Ok(Tree::Map(entries))
+    }
+}
+
+/// Deep-merge `overlay` into `base`.
+///
+/// Objects are merged key by key, any other value in `overlay` replaces the
+/// one in `base`.
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
@@ -0,0 +1,191 @@
+//! Reloading configuration files when they change
+
+use crate::migrate::{self, Migrations};
+use crate::utils::resolve_symlink;
+use crate::{Confy, ConfyError, Format, try_read_path};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// How long a file has to stay unchanged before it is reloaded.
+///
+/// Editors save in several steps, like writing a temporary file and renaming
+/// it over the original, which should result in a single reload.
+pub(crate) const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches a configuration file for changes, until it is dropped.
+///
+/// Returned by [`watch`] and [`watch_path`].
+///
+/// [`watch`]: fn.watch.html
+/// [`watch_path`]: fn.watch_path.html
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher").finish_non_exhaustive()
+    }
+}
+
+/// Reload an application configuration whenever its file changes
+///
+/// The file from [`get_configuration_file_path`] is watched, and once it
+/// stays unchanged for a short while after a change, it is loaded again like
+/// [`load`] does and `callback` receives the new configuration. If the
+/// changed file cannot be read or parsed, `callback` receives the error
+/// instead, so the application can keep its previous configuration. A
+/// removed file is not reported. The file is not created either, only its
+/// directory, as that is what is watched.
+///
+/// `callback` runs on a background thread until the returned [`Watcher`]
+/// is dropped. Requires the `watch` feature.
+///
+/// ```rust,no_run
+/// # use confy::ConfyError;
+/// # use serde_derive::{Serialize, Deserialize};
+/// # fn main() -> Result<(), ConfyError> {
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct MyConfig {
+///     verbose: bool,
+/// }
+///
+/// let _watcher = confy::watch("my-app-name", None, |cfg: Result<MyConfig, ConfyError>| {
+///     match cfg {
+///         Ok(cfg) => println!("reloaded, verbose: {}", cfg.verbose),
+///         Err(e) => eprintln!("keeping the previous configuration: {e}"),
+///     }
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`get_configuration_file_path`]: fn.get_configuration_file_path.html
+/// [`load`]: fn.load.html
+pub fn watch<'a, T, F>(
+    app_name: &str,
+    config_name: impl Into<Option<&'a str>>,
+    callback: F,
+) -> Result<Watcher, ConfyError>
+where
+    T: DeserializeOwned + 'static,
+    F: FnMut(Result<T, ConfyError>) + Send + 'static,
+{
+    Confy::global(app_name).watch(config_name, callback)
+}
+
+/// Reload an application configuration from a specified path whenever it changes
+///
+/// Like [`load_path`], the format is determined from the file extension. For
+/// more information on errors and behavior, see [`watch`]'s documentation.
+///
+/// [`load_path`]: fn.load_path.html
+/// [`watch`]: fn.watch.html
+pub fn watch_path<T, F>(path: impl AsRef<Path>, callback: F) -> Result<Watcher, ConfyError>
+where
+    T: DeserializeOwned + 'static,
+    F: FnMut(Result<T, ConfyError>) + Send + 'static,
+{
+    let format = Format::from_path(&path)?;
+    watch_path_format(path.as_ref(), format, None, callback)
+}
+
+/// Watch the file at `path` in the given format, upgrading it with
+/// `migrations` when reloading it, if there are any.
+pub(crate) fn watch_path_format<T, F>(
+    path: &Path,
+    format: Format,
+    migrations: Option<Migrations>,
+    mut callback: F,
+) -> Result<Watcher, ConfyError>
+where
+    T: DeserializeOwned + 'static,
+    F: FnMut(Result<T, ConfyError>) + Send + 'static,
+{
+    let target = resolve_symlink(path);
+    watch_changes(path, move |changed| {
+        let bytes = changed.and_then(|()| try_read_path(&target));
+        let cfg = bytes.and_then(|bytes| {
+            bytes
+                .map(|bytes| migrate::deserialize(&bytes, format, migrations.as_ref()))
+                .transpose()
+        });
+        match cfg {
+            Ok(Some(cfg)) => callback(Ok(cfg)),
+            Ok(None) => {}
+            Err(e) => callback(Err(e)),
+        }
+    })
+}
+
+/// Call `on_change` whenever the file at `path` settles after being changed,
+/// or with the error if watching it fails.
+pub(crate) fn watch_changes<F>(path: &Path, on_change: F) -> Result<Watcher, ConfyError>
+where
+    F: FnMut(Result<(), ConfyError>) + Send + 'static,
+{
+    let target = resolve_symlink(path);
+    // the file itself is replaced on every store, so its directory is watched,
+    // which has to exist for that
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    fs::create_dir_all(&dir).map_err(ConfyError::DirectoryCreationFailed)?;
+
+    let file_name = target.file_name().map(ToOwned::to_owned);
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let relevant = match &event {
+            // reading the file, not least when reloading it, changes nothing
+            Ok(event) => {
+                !event.kind.is_access()
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref())
+            }
+            Err(_) => true,
+        };
+        if relevant {
+            let _ = sender.send(event);
+        }
+    })
+    .map_err(ConfyError::WatchError)?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(ConfyError::WatchError)?;
+
+    thread::spawn(move || debounce(receiver, on_change));
+    Ok(Watcher { _watcher: watcher })
+}
+
+/// Call `on_change` once the changes reported by `events` settle.
+///
+/// Returns when the [`Watcher`] is dropped, which closes the channel.
+fn debounce<F>(events: Receiver<notify::Result<Event>>, mut on_change: F)
+where
+    F: FnMut(Result<(), ConfyError>),
+{
+    while let Ok(event) = events.recv() {
+        if let Err(e) = event {
+            on_change(Err(ConfyError::WatchError(e)));
+            continue;
+        }
+        loop {
+            match events.recv_timeout(DEBOUNCE) {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => on_change(Err(ConfyError::WatchError(e))),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        on_change(Ok(()));
+    }
+}